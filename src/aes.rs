@@ -1,147 +1,139 @@
-// Copyright (c) 2006-2009 Graydon Hoare
-// Copyright (c) 2009-2013 Mozilla Foundation
-
-// Permission is hereby granted, free of charge, to any
-// person obtaining a copy of this software and associated
-// documentation files (the "Software"), to deal in the
-// Software without restriction, including without
-// limitation the rights to use, copy, modify, merge,
-// publish, distribute, sublicense, and/or sell copies of
-// the Software, and to permit persons to whom the Software
-// is furnished to do so, subject to the following
-// conditions:
-
-// The above copyright notice and this permission notice
-// shall be included in all copies or substantial portions
-// of the Software.
-
-// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF
-// ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED
-// TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
-// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT
-// SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
-// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
-// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR
-// IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
-// DEALINGS IN THE SOFTWARE.
-//
-// Source: https://github.com/DaGenix/rust-crypto/blob/master/examples/symmetriccipher.rs#L87
-
-use crypto::{ symmetriccipher, buffer, aes, blockmodes };
-use crypto::buffer::{ ReadBuffer, WriteBuffer };
-
-const IV: [u8; 16] = [0u8; 16];
-
-pub fn encrypt_block(block: &[u8], key: &[u8]) -> Result<Vec<u8>, symmetriccipher::SymmetricCipherError> {
-    let mut encryptor = aes::cbc_encryptor(
-        aes::KeySize::KeySize256,
-        key,
-        &IV,
-        blockmodes::NoPadding
-    );
-
-    let mut final_result = Vec::<u8>::new();
-    let mut buffer = [0; 16];
-    let mut read_buffer = buffer::RefReadBuffer::new(block);
-    let mut write_buffer = buffer::RefWriteBuffer::new(&mut buffer);
-
-    loop {
-        let result = try!(encryptor.encrypt(&mut read_buffer, &mut write_buffer, true));
-        final_result.extend(write_buffer.take_read_buffer().take_remaining());
-        match result {
-            buffer::BufferResult::BufferUnderflow => break,
-            buffer::BufferResult::BufferOverflow => { }
+// AES-256 for the XTS mode. The key is expanded once per key and only for the direction it is used in,
+// blocks are processed in place.
+// AES-NI is used if the CPU supports it (detected at runtime), the constant-time software implementation otherwise.
+
+use crypto::aes::KeySize;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+use crypto::aesni::{AesNiDecryptor, AesNiEncryptor};
+use crypto::aessafe::{AesSafe256Decryptor, AesSafe256Encryptor};
+use crypto::symmetriccipher::{BlockDecryptor, BlockEncryptor};
+
+use cipher::{self, BlockCipher, Direction, BLOCK_SIZE, KEY_SIZE};
+use error::TcFinderError;
+
+// Key expanded for encryption or for decryption. XTS encrypts the tweak with the second key even when decrypting,
+// so each key needs only one of them.
+pub enum Aes256 {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    AesNiEncryptor(AesNiEncryptor),
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    AesNiDecryptor(AesNiDecryptor),
+    SoftwareEncryptor(AesSafe256Encryptor),
+    SoftwareDecryptor(AesSafe256Decryptor),
+}
+
+impl Aes256 {
+    pub fn new(key: &[u8], direction: Direction) -> Aes256 {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        {
+            if is_x86_feature_detected!("aes") {
+                return Aes256::aesni(key, direction);
+            }
+        }
+        Aes256::software(key, direction)
+    }
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    fn aesni(key: &[u8], direction: Direction) -> Aes256 {
+        assert!(key.len() == KEY_SIZE, "AES-256: key length != 32");
+        match direction {
+            Direction::Encrypt => Aes256::AesNiEncryptor(AesNiEncryptor::new(KeySize::KeySize256, key)),
+            Direction::Decrypt => Aes256::AesNiDecryptor(AesNiDecryptor::new(KeySize::KeySize256, key)),
         }
     }
 
-    Ok(final_result)
+    fn software(key: &[u8], direction: Direction) -> Aes256 {
+        assert!(key.len() == KEY_SIZE, "AES-256: key length != 32");
+        match direction {
+            Direction::Encrypt => Aes256::SoftwareEncryptor(AesSafe256Encryptor::new(key)),
+            Direction::Decrypt => Aes256::SoftwareDecryptor(AesSafe256Decryptor::new(key)),
+        }
+    }
 }
 
-pub fn decrypt_block(block: &[u8], key: &[u8]) -> Result<Vec<u8>, symmetriccipher::SymmetricCipherError> {
-    let mut decryptor = aes::cbc_decryptor(
-            aes::KeySize::KeySize256,
-            key,
-            &IV,
-            blockmodes::NoPadding
-    );
-
-    let mut final_result = Vec::<u8>::new();
-    let mut buffer = [0; 16];
-    let mut read_buffer = buffer::RefReadBuffer::new(block);
-    let mut write_buffer = buffer::RefWriteBuffer::new(&mut buffer);
-
-    loop {
-        let result = try!(decryptor.decrypt(&mut read_buffer, &mut write_buffer, true));
-        final_result.extend(write_buffer.take_read_buffer().take_remaining());
-        match result {
-            buffer::BufferResult::BufferUnderflow => break,
-            buffer::BufferResult::BufferOverflow => { }
+// Fails if the key was expanded for the other direction.
+impl BlockCipher for Aes256 {
+    fn encrypt_block(&self, block: &mut [u8]) -> Result<(), TcFinderError> {
+        cipher::check_block(block)?;
+        let mut input = [0u8; BLOCK_SIZE];
+        input.copy_from_slice(block);
+        match *self {
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Aes256::AesNiEncryptor(ref encryptor) => encryptor.encrypt_block(&input, block),
+            Aes256::SoftwareEncryptor(ref encryptor) => encryptor.encrypt_block(&input, block),
+            _ => return Err(TcFinderError::Crypto("AES-256: key is expanded for decryption.")),
         }
+        Ok(())
     }
 
-    Ok(final_result)
+    fn decrypt_block(&self, block: &mut [u8]) -> Result<(), TcFinderError> {
+        cipher::check_block(block)?;
+        let mut input = [0u8; BLOCK_SIZE];
+        input.copy_from_slice(block);
+        match *self {
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Aes256::AesNiDecryptor(ref decryptor) => decryptor.decrypt_block(&input, block),
+            Aes256::SoftwareDecryptor(ref decryptor) => decryptor.decrypt_block(&input, block),
+            _ => return Err(TcFinderError::Crypto("AES-256: key is expanded for encryption.")),
+        }
+        Ok(())
+    }
 }
 
-
 #[cfg(test)]
 mod tests {
+    #[cfg(feature = "nightly")]
     use test::Bencher;
 
-    #[test]
-    fn test_encrypt_aes() {
-        let key_str = "a0k5lg03mvh2laoqwxcbmtbdksloew58";
-        let key_bytes = key_str.as_bytes();
-
-        let plain = [99u8; 16];
-        let expected = [0x0b, 0x06, 0xb1, 0x75,
-                        0x2c, 0x34, 0x4b, 0x37,
-                        0x25, 0xda, 0x61, 0x9f,
-                        0x37, 0x35, 0x08, 0x52];
-        
-        let result = super::encrypt_block(&plain, key_bytes).unwrap();
-        assert_eq!(result, expected);
+    use super::Aes256;
+    use cipher::{BlockCipher, Direction};
+
+    const KEY: &[u8] = b"a0k5lg03mvh2laoqwxcbmtbdksloew58";
+    const PLAIN: [u8; 16] = [99u8; 16];
+    const ENCRYPTED: [u8; 16] = [0x0b, 0x06, 0xb1, 0x75,
+                                 0x2c, 0x34, 0x4b, 0x37,
+                                 0x25, 0xda, 0x61, 0x9f,
+                                 0x37, 0x35, 0x08, 0x52];
+
+    fn check<F: Fn(&[u8], Direction) -> Aes256>(new: F) {
+        let mut block = PLAIN;
+        new(KEY, Direction::Encrypt).encrypt_block(&mut block).unwrap();
+        assert_eq!(block, ENCRYPTED);
+        new(KEY, Direction::Decrypt).decrypt_block(&mut block).unwrap();
+        assert_eq!(block, PLAIN);
+        assert!(new(KEY, Direction::Decrypt).encrypt_block(&mut block).is_err());
+        assert!(new(KEY, Direction::Encrypt).decrypt_block(&mut block).is_err());
     }
-    
-    #[test]
-    fn test_decrypt_aes() {
-        let key_str = "a0k5lg03mvh2laoqwxcbmtbdksloew58";
-        let key_bytes = key_str.as_bytes();
-
-        let encrypted = [0x0b, 0x06, 0xb1, 0x75,
-                        0x2c, 0x34, 0x4b, 0x37,
-                        0x25, 0xda, 0x61, 0x9f,
-                        0x37, 0x35, 0x08, 0x52];
 
-        let expected = [99u8; 16];
+    #[test]
+    fn test_aes() {
+        check(Aes256::new);
+        check(Aes256::software);
+    }
 
-        let result = super::decrypt_block(&encrypted, key_bytes).unwrap();
-        assert_eq!(result, expected);
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    #[test]
+    fn test_aesni() {
+        if is_x86_feature_detected!("aes") {
+            check(Aes256::aesni);
+        }
     }
 
+    #[cfg(feature = "nightly")]
     #[bench]
     fn bench_encrypt_aes(b: &mut Bencher) {
-        let key_str = "a0k5lg03mvh2laoqwxcbmtbdksloew58";
-        let key_bytes = key_str.as_bytes();
+        let aes = Aes256::new(KEY, Direction::Encrypt);
+        let mut block = PLAIN;
 
-        let plain = [99u8; 16];
-        
-        b.iter(|| {super::encrypt_block(&plain, key_bytes).unwrap();});
+        b.iter(|| aes.encrypt_block(&mut block).unwrap());
     }
 
+    #[cfg(feature = "nightly")]
     #[bench]
     fn bench_decrypt_aes(b: &mut Bencher) {
-        let key_str = "a0k5lg03mvh2laoqwxcbmtbdksloew58";
-        let key_bytes = key_str.as_bytes();
+        let aes = Aes256::new(KEY, Direction::Decrypt);
+        let mut block = ENCRYPTED;
 
-        let encrypted = [0x0b, 0x06, 0xb1, 0x75,
-                        0x2c, 0x34, 0x4b, 0x37,
-                        0x25, 0xda, 0x61, 0x9f,
-                        0x37, 0x35, 0x08, 0x52];
-
-
-        b.iter(|| { super::decrypt_block(&encrypted, key_bytes).unwrap(); });
+        b.iter(|| aes.decrypt_block(&mut block).unwrap());
     }
 }
-
-
-
@@ -0,0 +1,209 @@
+// Camellia with 256 bit keys as specified in RFC 3713. Blocks and keys are big-endian.
+
+use cipher::{self, BlockCipher};
+use error::TcFinderError;
+
+const SIGMA: [u64; 6] = [
+    0xA09E_667F_3BCC_908B,
+    0xB67A_E858_4CAA_73B2,
+    0xC6EF_372F_E94F_82BE,
+    0x54FF_53A5_F1D3_6F1C,
+    0x10E5_27FA_DE68_2D1D,
+    0xB056_88C2_B3E6_C1FD,
+];
+
+// SBOX2, SBOX3 and SBOX4 are rotations of SBOX1.
+const SBOX1: [u8; 256] = [
+    0x70, 0x82, 0x2C, 0xEC, 0xB3, 0x27, 0xC0, 0xE5, 0xE4, 0x85, 0x57, 0x35, 0xEA, 0x0C, 0xAE, 0x41,
+    0x23, 0xEF, 0x6B, 0x93, 0x45, 0x19, 0xA5, 0x21, 0xED, 0x0E, 0x4F, 0x4E, 0x1D, 0x65, 0x92, 0xBD,
+    0x86, 0xB8, 0xAF, 0x8F, 0x7C, 0xEB, 0x1F, 0xCE, 0x3E, 0x30, 0xDC, 0x5F, 0x5E, 0xC5, 0x0B, 0x1A,
+    0xA6, 0xE1, 0x39, 0xCA, 0xD5, 0x47, 0x5D, 0x3D, 0xD9, 0x01, 0x5A, 0xD6, 0x51, 0x56, 0x6C, 0x4D,
+    0x8B, 0x0D, 0x9A, 0x66, 0xFB, 0xCC, 0xB0, 0x2D, 0x74, 0x12, 0x2B, 0x20, 0xF0, 0xB1, 0x84, 0x99,
+    0xDF, 0x4C, 0xCB, 0xC2, 0x34, 0x7E, 0x76, 0x05, 0x6D, 0xB7, 0xA9, 0x31, 0xD1, 0x17, 0x04, 0xD7,
+    0x14, 0x58, 0x3A, 0x61, 0xDE, 0x1B, 0x11, 0x1C, 0x32, 0x0F, 0x9C, 0x16, 0x53, 0x18, 0xF2, 0x22,
+    0xFE, 0x44, 0xCF, 0xB2, 0xC3, 0xB5, 0x7A, 0x91, 0x24, 0x08, 0xE8, 0xA8, 0x60, 0xFC, 0x69, 0x50,
+    0xAA, 0xD0, 0xA0, 0x7D, 0xA1, 0x89, 0x62, 0x97, 0x54, 0x5B, 0x1E, 0x95, 0xE0, 0xFF, 0x64, 0xD2,
+    0x10, 0xC4, 0x00, 0x48, 0xA3, 0xF7, 0x75, 0xDB, 0x8A, 0x03, 0xE6, 0xDA, 0x09, 0x3F, 0xDD, 0x94,
+    0x87, 0x5C, 0x83, 0x02, 0xCD, 0x4A, 0x90, 0x33, 0x73, 0x67, 0xF6, 0xF3, 0x9D, 0x7F, 0xBF, 0xE2,
+    0x52, 0x9B, 0xD8, 0x26, 0xC8, 0x37, 0xC6, 0x3B, 0x81, 0x96, 0x6F, 0x4B, 0x13, 0xBE, 0x63, 0x2E,
+    0xE9, 0x79, 0xA7, 0x8C, 0x9F, 0x6E, 0xBC, 0x8E, 0x29, 0xF5, 0xF9, 0xB6, 0x2F, 0xFD, 0xB4, 0x59,
+    0x78, 0x98, 0x06, 0x6A, 0xE7, 0x46, 0x71, 0xBA, 0xD4, 0x25, 0xAB, 0x42, 0x88, 0xA2, 0x8D, 0xFA,
+    0x72, 0x07, 0xB9, 0x55, 0xF8, 0xEE, 0xAC, 0x0A, 0x36, 0x49, 0x2A, 0x68, 0x3C, 0x38, 0xF1, 0xA4,
+    0x40, 0x28, 0xD3, 0x7B, 0xBB, 0xC9, 0x43, 0xC1, 0x15, 0xE3, 0xAD, 0xF4, 0x77, 0xC7, 0x80, 0x9E,
+];
+
+const ROUNDS: usize = 24;
+
+pub struct Camellia {
+    kw: [u64; 4],
+    k: [u64; ROUNDS],
+    ke: [u64; 6],
+}
+
+impl Camellia {
+    pub fn new(key: &[u8]) -> Camellia {
+        assert!(key.len() == 32, "Camellia: key length != 32");
+        let kl = read_u128_be(&key[..16]);
+        let kr = read_u128_be(&key[16..]);
+
+        let mut d1 = ((kl ^ kr) >> 64) as u64;
+        let mut d2 = (kl ^ kr) as u64;
+        d2 ^= f(d1, SIGMA[0]);
+        d1 ^= f(d2, SIGMA[1]);
+        d1 ^= (kl >> 64) as u64;
+        d2 ^= kl as u64;
+        d2 ^= f(d1, SIGMA[2]);
+        d1 ^= f(d2, SIGMA[3]);
+        let ka = u128::from(d1) << 64 | u128::from(d2);
+
+        let mut d1 = ((ka ^ kr) >> 64) as u64;
+        let mut d2 = (ka ^ kr) as u64;
+        d2 ^= f(d1, SIGMA[4]);
+        d1 ^= f(d2, SIGMA[5]);
+        let kb = u128::from(d1) << 64 | u128::from(d2);
+
+        // Each subkey pair is the high and low half of a rotated key.
+        let halves = |x: u128, n: u32| {
+            let rotated = x.rotate_left(n);
+            [(rotated >> 64) as u64, rotated as u64]
+        };
+        let pairs = [
+            halves(kb, 0), halves(kr, 15), halves(ka, 15), halves(kb, 30), halves(kl, 45), halves(ka, 45),
+            halves(kr, 60), halves(kb, 60), halves(kl, 77), halves(kr, 94), halves(ka, 94), halves(kl, 111),
+        ];
+        let mut k = [0u64; ROUNDS];
+        for (i, pair) in pairs.iter().enumerate() {
+            k[2 * i] = pair[0];
+            k[2 * i + 1] = pair[1];
+        }
+        let (kw12, kw34) = (halves(kl, 0), halves(kb, 111));
+        let (ke12, ke34, ke56) = (halves(kr, 30), halves(kl, 60), halves(ka, 77));
+
+        Camellia {
+            kw: [kw12[0], kw12[1], kw34[0], kw34[1]],
+            k,
+            ke: [ke12[0], ke12[1], ke34[0], ke34[1], ke56[0], ke56[1]],
+        }
+    }
+
+    // Decryption is encryption with the subkeys in reverse order.
+    fn process(block: &mut [u8], kw: [u64; 4], k: &[u64; ROUNDS], ke: [u64; 6]) {
+        assert!(block.len() == 16, "Camellia: block length != 16");
+        let m = read_u128_be(block);
+        let mut d1 = (m >> 64) as u64 ^ kw[0];
+        let mut d2 = m as u64 ^ kw[1];
+        for i in 0..ROUNDS / 2 {
+            d2 ^= f(d1, k[2 * i]);
+            d1 ^= f(d2, k[2 * i + 1]);
+            // FL layer after every 6 rounds except the last ones.
+            if i % 3 == 2 && i < ROUNDS / 2 - 1 {
+                d1 = fl(d1, ke[2 * (i / 3)]);
+                d2 = fl_inv(d2, ke[2 * (i / 3) + 1]);
+            }
+        }
+        d2 ^= kw[2];
+        d1 ^= kw[3];
+        let c = u128::from(d2) << 64 | u128::from(d1);
+        block.copy_from_slice(&c.to_be_bytes());
+    }
+}
+
+impl BlockCipher for Camellia {
+    fn encrypt_block(&self, block: &mut [u8]) -> Result<(), TcFinderError> {
+        cipher::check_block(block)?;
+        Camellia::process(block, self.kw, &self.k, self.ke);
+        Ok(())
+    }
+
+    fn decrypt_block(&self, block: &mut [u8]) -> Result<(), TcFinderError> {
+        cipher::check_block(block)?;
+        let mut k = self.k;
+        k.reverse();
+        let ke = [self.ke[5], self.ke[4], self.ke[3], self.ke[2], self.ke[1], self.ke[0]];
+        Camellia::process(block, [self.kw[2], self.kw[3], self.kw[0], self.kw[1]], &k, ke);
+        Ok(())
+    }
+}
+
+fn f(x: u64, k: u64) -> u64 {
+    let t = (x ^ k).to_be_bytes();
+    let t = [sbox1(t[0]), sbox2(t[1]), sbox3(t[2]), sbox4(t[3]), sbox2(t[4]), sbox3(t[5]), sbox4(t[6]), sbox1(t[7])];
+    let y = [
+        t[0] ^ t[2] ^ t[3] ^ t[5] ^ t[6] ^ t[7],
+        t[0] ^ t[1] ^ t[3] ^ t[4] ^ t[6] ^ t[7],
+        t[0] ^ t[1] ^ t[2] ^ t[4] ^ t[5] ^ t[7],
+        t[1] ^ t[2] ^ t[3] ^ t[4] ^ t[5] ^ t[6],
+        t[0] ^ t[1] ^ t[5] ^ t[6] ^ t[7],
+        t[1] ^ t[2] ^ t[4] ^ t[6] ^ t[7],
+        t[2] ^ t[3] ^ t[4] ^ t[5] ^ t[7],
+        t[0] ^ t[3] ^ t[4] ^ t[5] ^ t[6],
+    ];
+    u64::from_be_bytes(y)
+}
+
+fn fl(x: u64, k: u64) -> u64 {
+    let (mut x1, mut x2) = ((x >> 32) as u32, x as u32);
+    let (k1, k2) = ((k >> 32) as u32, k as u32);
+    x2 ^= (x1 & k1).rotate_left(1);
+    x1 ^= x2 | k2;
+    u64::from(x1) << 32 | u64::from(x2)
+}
+
+fn fl_inv(y: u64, k: u64) -> u64 {
+    let (mut y1, mut y2) = ((y >> 32) as u32, y as u32);
+    let (k1, k2) = ((k >> 32) as u32, k as u32);
+    y1 ^= y2 | k2;
+    y2 ^= (y1 & k1).rotate_left(1);
+    u64::from(y1) << 32 | u64::from(y2)
+}
+
+fn sbox1(x: u8) -> u8 {
+    SBOX1[x as usize]
+}
+
+fn sbox2(x: u8) -> u8 {
+    SBOX1[x as usize].rotate_left(1)
+}
+
+fn sbox3(x: u8) -> u8 {
+    SBOX1[x as usize].rotate_left(7)
+}
+
+fn sbox4(x: u8) -> u8 {
+    SBOX1[x.rotate_left(1) as usize]
+}
+
+fn read_u128_be(bytes: &[u8]) -> u128 {
+    bytes.iter().fold(0, |acc, &b| acc << 8 | u128::from(b))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::Camellia;
+    use cipher::BlockCipher;
+
+    fn hex_str_to_vec(s: &str) -> Vec<u8> {
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i+2], 16).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_camellia() {
+        let test_cases = [
+            // RFC 3713, 256 bit key
+            ("0123456789abcdeffedcba987654321000112233445566778899aabbccddeeff",
+             "0123456789abcdeffedcba9876543210", "9acc237dff16d76c20ef7c919e3a7509"),
+            ("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f",
+             "00112233445566778899aabbccddeeff", "2edf1f3418d53b88841fc8985fb1ecf2"),
+        ];
+
+        for &(key, plain, cipher) in &test_cases {
+            let camellia = Camellia::new(&hex_str_to_vec(key));
+            let mut block = hex_str_to_vec(plain);
+            camellia.encrypt_block(&mut block).unwrap();
+            assert_eq!(block, hex_str_to_vec(cipher));
+            camellia.decrypt_block(&mut block).unwrap();
+            assert_eq!(block, hex_str_to_vec(plain));
+        }
+    }
+}
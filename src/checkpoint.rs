@@ -0,0 +1,230 @@
+// Progress of a long scan, saved periodically so an interrupted scan can be resumed with the same configuration.
+//
+// Text file, one entry per line:
+//   tcfinder checkpoint 1
+//   config <crc of the scan configuration, hex>
+//   completed <first sector> <last sector>
+//   found <sector>
+//   unreadable <sector>
+
+use std::cmp;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Write};
+
+use crc32;
+use error::TcFinderError;
+use tcfinder::ScanConfig;
+
+const FIRST_LINE: &str = "tcfinder checkpoint 1";
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Checkpoint {
+    pub config_hash: u32,
+    // Inclusive sector ranges that were scanned completely, sorted and merged.
+    pub completed: Vec<(u64, u64)>,
+    // Sectors of the headers found so far, they are checked again when resuming.
+    pub found_sectors: Vec<u64>,
+    pub unreadable_sectors: Vec<u64>,
+}
+
+impl Checkpoint {
+    pub fn new(config: &ScanConfig) -> Checkpoint {
+        Checkpoint { config_hash: config_hash(config), ..Checkpoint::default() }
+    }
+
+    // Loads the checkpoint and checks that it was saved for the same configuration.
+    pub fn load(path: &str, config: &ScanConfig) -> Result<Checkpoint, TcFinderError> {
+        let file = File::open(path).map_err(|e| TcFinderError::file(path, e))?;
+        let mut lines = Vec::new();
+        for line in BufReader::new(file).lines() {
+            lines.push(line.map_err(|e| TcFinderError::file(path, e))?);
+        }
+        let checkpoint = Checkpoint::parse(&lines).map_err(TcFinderError::Checkpoint)?;
+        if checkpoint.config_hash != config_hash(config) {
+            return Err(TcFinderError::Checkpoint("The checkpoint was saved for a different scan configuration."));
+        }
+        Ok(checkpoint)
+    }
+
+    fn parse<S: AsRef<str>>(lines: &[S]) -> Result<Checkpoint, &'static str> {
+        if lines.first().map(|line| line.as_ref()) != Some(FIRST_LINE) {
+            return Err("Not a tcfinder checkpoint file.");
+        }
+        let mut checkpoint = Checkpoint::default();
+        for line in &lines[1..] {
+            let fields: Vec<&str> = line.as_ref().split_whitespace().collect();
+            let number = |i: usize| -> Result<u64, &'static str> {
+                fields.get(i).and_then(|field| field.parse().ok()).ok_or("Invalid line in checkpoint file.")
+            };
+            match fields.first() {
+                None => (),
+                Some(&"config") => {
+                    let hash = fields.get(1).and_then(|field| u32::from_str_radix(field, 16).ok());
+                    checkpoint.config_hash = hash.ok_or("Invalid line in checkpoint file.")?;
+                }
+                Some(&"completed") => checkpoint.add_completed(number(1)?, number(2)?),
+                Some(&"found") => checkpoint.found_sectors.push(number(1)?),
+                Some(&"unreadable") => checkpoint.unreadable_sectors.push(number(1)?),
+                Some(_) => return Err("Invalid line in checkpoint file."),
+            }
+        }
+        Ok(checkpoint)
+    }
+
+    // Writes a temporary file first and renames it, an interruption while saving keeps the previous checkpoint.
+    pub fn save(&self, path: &str) -> Result<(), TcFinderError> {
+        let mut text = format!("{}\nconfig {:08x}\n", FIRST_LINE, self.config_hash);
+        for &(start, end) in &self.completed {
+            text += &format!("completed {} {}\n", start, end);
+        }
+        for sector in &self.found_sectors {
+            text += &format!("found {}\n", sector);
+        }
+        for sector in &self.unreadable_sectors {
+            text += &format!("unreadable {}\n", sector);
+        }
+
+        let temporary = format!("{}.tmp", path);
+        File::create(&temporary)
+            .and_then(|mut file| file.write_all(text.as_bytes()).and_then(|_| file.sync_all()))
+            .map_err(|e| TcFinderError::file(&temporary, e))?;
+        fs::rename(&temporary, path).map_err(|e| TcFinderError::file(path, e))
+    }
+
+    pub fn add_completed(&mut self, start: u64, end: u64) {
+        self.completed.push((start, end));
+        self.completed.sort();
+        let mut merged: Vec<(u64, u64)> = Vec::new();
+        for &(start, end) in &self.completed {
+            match merged.last_mut() {
+                Some(last) if start <= last.1.saturating_add(1) => last.1 = cmp::max(last.1, end),
+                _ => merged.push((start, end)),
+            }
+        }
+        self.completed = merged;
+    }
+
+    // The parts of the sector ranges that are not completed yet. Invalid ranges are kept for the scan to reject.
+    pub fn remaining(&self, sector_ranges: &[(u64, u64)]) -> Vec<(u64, u64)> {
+        let mut remaining = Vec::new();
+        for &(start, end) in sector_ranges {
+            if start > end {
+                remaining.push((start, end));
+                continue;
+            }
+            let mut next = start;
+            for &(done_start, done_end) in &self.completed {
+                if done_end < next || done_start > end {
+                    continue;
+                }
+                if done_start > next {
+                    remaining.push((next, done_start - 1));
+                }
+                next = done_end.saturating_add(1);
+                if next > end {
+                    break;
+                }
+            }
+            if next <= end {
+                remaining.push((next, end));
+            }
+        }
+        remaining
+    }
+}
+
+// Identifies the configuration: sector ranges, passwords, key derivations and encryption algorithms.
+pub fn config_hash(config: &ScanConfig) -> u32 {
+    let mut data = Vec::new();
+    for &(start, end) in &config.sector_ranges {
+        data.extend_from_slice(&start.to_le_bytes());
+        data.extend_from_slice(&end.to_le_bytes());
+    }
+    for password in &config.passwords {
+        data.extend_from_slice(&(password.len() as u64).to_le_bytes());
+        data.extend_from_slice(password);
+    }
+    // Only bytes that do not change with the code: the format magic, the names of the PRFs and algorithms as shown
+    // by TrueCrypt and the iteration count (which also covers PIM and system encryption).
+    for derivation in &config.derivations {
+        data.extend_from_slice(&derivation.format.magic());
+        push_name(&mut data, derivation.prf.name());
+        data.extend_from_slice(&derivation.iterations.to_le_bytes());
+    }
+    for algorithm in &config.algorithms {
+        push_name(&mut data, algorithm.name());
+    }
+    crc32::checksum(&data)
+}
+
+fn push_name(data: &mut Vec<u8>, name: &str) {
+    data.extend_from_slice(&(name.len() as u64).to_le_bytes());
+    data.extend_from_slice(name.as_bytes());
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::Checkpoint;
+    use cipher::EncryptionAlgorithm;
+    use kdf::{KeyDerivation, Prf};
+    use tcfinder::ScanConfig;
+    use volumeheader::VolumeFormat;
+
+    fn config() -> ScanConfig {
+        let derivation = KeyDerivation::new(VolumeFormat::TrueCrypt, Prf::Ripemd160, None, false).unwrap();
+        ScanConfig::new(vec![(0, 999), (5000, 5999)], vec![b"p4ssword".to_vec()], vec![derivation])
+    }
+
+    #[test]
+    fn test_remaining() {
+        let mut checkpoint = Checkpoint::new(&config());
+        assert_eq!(checkpoint.remaining(&[(0, 999), (5000, 5999)]), [(0, 999), (5000, 5999)]);
+
+        checkpoint.add_completed(0, 99);
+        checkpoint.add_completed(200, 299);
+        checkpoint.add_completed(100, 149);
+        checkpoint.add_completed(5000, 5999);
+        assert_eq!(checkpoint.completed, [(0, 149), (200, 299), (5000, 5999)]);
+        assert_eq!(checkpoint.remaining(&[(0, 999), (5000, 5999)]), [(150, 199), (300, 999)]);
+        assert_eq!(checkpoint.remaining(&[(100, 250)]), [(150, 199)]);
+        assert!(checkpoint.remaining(&[(5500, 5600)]).is_empty());
+        assert_eq!(checkpoint.remaining(&[(30, 10)]), [(30, 10)]);
+    }
+
+    #[test]
+    fn test_save_load() {
+        let config = config();
+        let mut checkpoint = Checkpoint::new(&config);
+        checkpoint.add_completed(0, 999);
+        checkpoint.add_completed(5000, 5099);
+        checkpoint.found_sectors = vec![20, 5020];
+        checkpoint.unreadable_sectors = vec![42];
+
+        let path = env::temp_dir().join("tcfinder_test_checkpoint");
+        let path = path.to_str().unwrap();
+        checkpoint.save(path).unwrap();
+        assert_eq!(Checkpoint::load(path, &config).unwrap(), checkpoint);
+
+        let other = ScanConfig { algorithms: vec![EncryptionAlgorithm::Aes], ..config.clone() };
+        assert!(Checkpoint::load(path, &other).is_err());
+        let other = ScanConfig::new(vec![(0, 999)], config.passwords.clone(), config.derivations.clone());
+        assert!(Checkpoint::load(path, &other).is_err());
+        fs::remove_file(path).unwrap();
+
+        assert!(Checkpoint::parse(&["not a checkpoint"]).is_err());
+        assert!(Checkpoint::parse(&[super::FIRST_LINE, "completed 1"]).is_err());
+        assert!(Checkpoint::parse(&[super::FIRST_LINE, "config xyz"]).is_err());
+    }
+
+    #[test]
+    fn test_config_hash() {
+        // Saved checkpoints only stay valid if the hash of the same configuration never changes.
+        let config = ScanConfig { algorithms: vec![EncryptionAlgorithm::Aes, EncryptionAlgorithm::SerpentTwofishAes], ..config() };
+        assert_eq!(super::config_hash(&config), 0xd3f0_b5a8);
+        let derivation = KeyDerivation::new(VolumeFormat::TrueCrypt, Prf::Ripemd160, None, true).unwrap();
+        assert_ne!(super::config_hash(&ScanConfig { derivations: vec![derivation], ..config.clone() }), super::config_hash(&config));
+    }
+}
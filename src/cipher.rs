@@ -0,0 +1,315 @@
+// Block ciphers and the cipher cascades TrueCrypt and VeraCrypt encrypt volumes with.
+
+use aes::Aes256;
+use camellia::Camellia;
+use error::TcFinderError;
+use kuznyechik::Kuznyechik;
+use serpent::Serpent;
+use twofish::Twofish;
+use volumeheader::VolumeFormat;
+use xts;
+
+pub const BLOCK_SIZE: usize = 16;
+pub const KEY_SIZE: usize = 32;
+
+// 128 bit block cipher, blocks are processed in place.
+// Fails if the block is not 16 bytes or the key is not expanded for the direction.
+pub trait BlockCipher {
+    fn encrypt_block(&self, block: &mut [u8]) -> Result<(), TcFinderError>;
+    fn decrypt_block(&self, block: &mut [u8]) -> Result<(), TcFinderError>;
+}
+
+// Checked by the BlockCipher implementations before touching the block.
+pub fn check_block(block: &[u8]) -> Result<(), TcFinderError> {
+    if block.len() != BLOCK_SIZE {
+        return Err(TcFinderError::Crypto("Block length is not 16 bytes."));
+    }
+    Ok(())
+}
+
+// Direction a key is expanded for. Ciphers with separate schedules (AES) only expand this one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Encrypt,
+    Decrypt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cipher {
+    Aes,
+    Serpent,
+    Twofish,
+    // VeraCrypt only
+    Camellia,
+    // VeraCrypt only
+    Kuznyechik,
+}
+
+impl Cipher {
+    pub fn new_block_cipher(&self, key: &[u8], direction: Direction) -> Result<Box<dyn BlockCipher>, TcFinderError> {
+        if key.len() != KEY_SIZE {
+            return Err(TcFinderError::Crypto("Key length is not 32 bytes."));
+        }
+        Ok(match *self {
+            Cipher::Aes => Box::new(Aes256::new(key, direction)),
+            Cipher::Serpent => Box::new(Serpent::new(key)),
+            Cipher::Twofish => Box::new(Twofish::new(key)),
+            Cipher::Camellia => Box::new(Camellia::new(key)),
+            Cipher::Kuznyechik => Box::new(Kuznyechik::new(key)),
+        })
+    }
+}
+
+// Encryption algorithm of a volume: a single cipher or a cascade, each cipher in XTS mode with its own keys.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EncryptionAlgorithm {
+    Aes,
+    Serpent,
+    Twofish,
+    AesTwofish,
+    AesTwofishSerpent,
+    SerpentAes,
+    SerpentTwofishAes,
+    TwofishSerpent,
+    // VeraCrypt only
+    Camellia,
+    Kuznyechik,
+    CamelliaKuznyechik,
+    CamelliaSerpent,
+    KuznyechikAes,
+    KuznyechikSerpentCamellia,
+    KuznyechikTwofish,
+}
+
+// TrueCrypt algorithms first, VeraCrypt supports all of them.
+pub const ALL_ALGORITHMS: [EncryptionAlgorithm; 15] = [
+    EncryptionAlgorithm::Aes,
+    EncryptionAlgorithm::Serpent,
+    EncryptionAlgorithm::Twofish,
+    EncryptionAlgorithm::AesTwofish,
+    EncryptionAlgorithm::AesTwofishSerpent,
+    EncryptionAlgorithm::SerpentAes,
+    EncryptionAlgorithm::SerpentTwofishAes,
+    EncryptionAlgorithm::TwofishSerpent,
+    EncryptionAlgorithm::Camellia,
+    EncryptionAlgorithm::Kuznyechik,
+    EncryptionAlgorithm::CamelliaKuznyechik,
+    EncryptionAlgorithm::CamelliaSerpent,
+    EncryptionAlgorithm::KuznyechikAes,
+    EncryptionAlgorithm::KuznyechikSerpentCamellia,
+    EncryptionAlgorithm::KuznyechikTwofish,
+];
+const TRUECRYPT_ALGORITHM_COUNT: usize = 8;
+
+// Encryption algorithms a volume of the given format can be encrypted with.
+pub fn algorithms(format: VolumeFormat) -> &'static [EncryptionAlgorithm] {
+    match format {
+        VolumeFormat::TrueCrypt => &ALL_ALGORITHMS[..TRUECRYPT_ALGORITHM_COUNT],
+        VolumeFormat::VeraCrypt => &ALL_ALGORITHMS,
+    }
+}
+
+// Comma separated algorithm names (e.g. 'AES,Serpent-Twofish-AES'), or 'all'.
+pub fn parse_algorithms(names: &str) -> Result<Vec<EncryptionAlgorithm>, TcFinderError> {
+    if names.eq_ignore_ascii_case("all") {
+        return Ok(ALL_ALGORITHMS.to_vec());
+    }
+    names.split(',').map(|name| {
+        EncryptionAlgorithm::from_name(name.trim()).ok_or(TcFinderError::Crypto("Unknown encryption algorithm."))
+    }).collect()
+}
+
+impl EncryptionAlgorithm {
+    // Name as shown by TrueCrypt and VeraCrypt.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            EncryptionAlgorithm::Aes => "AES",
+            EncryptionAlgorithm::Serpent => "Serpent",
+            EncryptionAlgorithm::Twofish => "Twofish",
+            EncryptionAlgorithm::AesTwofish => "AES-Twofish",
+            EncryptionAlgorithm::AesTwofishSerpent => "AES-Twofish-Serpent",
+            EncryptionAlgorithm::SerpentAes => "Serpent-AES",
+            EncryptionAlgorithm::SerpentTwofishAes => "Serpent-Twofish-AES",
+            EncryptionAlgorithm::TwofishSerpent => "Twofish-Serpent",
+            EncryptionAlgorithm::Camellia => "Camellia",
+            EncryptionAlgorithm::Kuznyechik => "Kuznyechik",
+            EncryptionAlgorithm::CamelliaKuznyechik => "Camellia-Kuznyechik",
+            EncryptionAlgorithm::CamelliaSerpent => "Camellia-Serpent",
+            EncryptionAlgorithm::KuznyechikAes => "Kuznyechik-AES",
+            EncryptionAlgorithm::KuznyechikSerpentCamellia => "Kuznyechik-Serpent-Camellia",
+            EncryptionAlgorithm::KuznyechikTwofish => "Kuznyechik-Twofish",
+        }
+    }
+
+    // Algorithm by its name, case insensitive.
+    pub fn from_name(name: &str) -> Option<EncryptionAlgorithm> {
+        ALL_ALGORITHMS.iter().cloned().find(|algorithm| algorithm.name().eq_ignore_ascii_case(name))
+    }
+
+    // Ciphers in the order they are applied when encrypting, which is the reverse of the name.
+    // AES-Twofish encrypts with Twofish first, then with AES.
+    pub fn ciphers(&self) -> &'static [Cipher] {
+        match *self {
+            EncryptionAlgorithm::Aes => &[Cipher::Aes],
+            EncryptionAlgorithm::Serpent => &[Cipher::Serpent],
+            EncryptionAlgorithm::Twofish => &[Cipher::Twofish],
+            EncryptionAlgorithm::AesTwofish => &[Cipher::Twofish, Cipher::Aes],
+            EncryptionAlgorithm::AesTwofishSerpent => &[Cipher::Serpent, Cipher::Twofish, Cipher::Aes],
+            EncryptionAlgorithm::SerpentAes => &[Cipher::Aes, Cipher::Serpent],
+            EncryptionAlgorithm::SerpentTwofishAes => &[Cipher::Aes, Cipher::Twofish, Cipher::Serpent],
+            EncryptionAlgorithm::TwofishSerpent => &[Cipher::Serpent, Cipher::Twofish],
+            EncryptionAlgorithm::Camellia => &[Cipher::Camellia],
+            EncryptionAlgorithm::Kuznyechik => &[Cipher::Kuznyechik],
+            EncryptionAlgorithm::CamelliaKuznyechik => &[Cipher::Kuznyechik, Cipher::Camellia],
+            EncryptionAlgorithm::CamelliaSerpent => &[Cipher::Serpent, Cipher::Camellia],
+            EncryptionAlgorithm::KuznyechikAes => &[Cipher::Aes, Cipher::Kuznyechik],
+            EncryptionAlgorithm::KuznyechikSerpentCamellia => &[Cipher::Camellia, Cipher::Serpent, Cipher::Kuznyechik],
+            EncryptionAlgorithm::KuznyechikTwofish => &[Cipher::Twofish, Cipher::Kuznyechik],
+        }
+    }
+
+    // Bytes of the key pool used: primary and secondary key of every cipher.
+    pub fn key_size(&self) -> usize {
+        2 * KEY_SIZE * self.ciphers().len()
+    }
+
+    // Expands the keys of all ciphers from the key pool, the primary keys for the given direction.
+    // Key layout: primary keys of the ciphers in encryption order, then the secondary keys in the same order.
+    pub fn expand_keys(&self, keypool: &[u8], direction: Direction) -> Result<ExpandedKeys, TcFinderError> {
+        if keypool.len() < self.key_size() {
+            return Err(TcFinderError::Crypto("Key pool is too small for the encryption algorithm."));
+        }
+        let count = self.ciphers().len();
+        let ciphers = self.ciphers().iter().enumerate().map(|(i, cipher)| {
+            let key1 = i * KEY_SIZE;
+            let key2 = (count + i) * KEY_SIZE;
+            // The secondary key only encrypts the tweak.
+            Ok((cipher.new_block_cipher(&keypool[key1..key1 + KEY_SIZE], direction)?,
+                cipher.new_block_cipher(&keypool[key2..key2 + KEY_SIZE], Direction::Encrypt)?))
+        }).collect::<Result<_, TcFinderError>>()?;
+        Ok(ExpandedKeys { ciphers })
+    }
+
+    // Decrypts a data unit in place, the ciphers are undone in reverse order.
+    pub fn decrypt_data_unit(&self, keypool: &[u8], data_unit: u64, data: &mut [u8]) -> Result<(), TcFinderError> {
+        self.expand_keys(keypool, Direction::Decrypt)?.decrypt_data_unit(data_unit, data)
+    }
+
+    // Encrypts a data unit in place. Counterpart of decrypt_data_unit.
+    pub fn encrypt_data_unit(&self, keypool: &[u8], data_unit: u64, data: &mut [u8]) -> Result<(), TcFinderError> {
+        self.expand_keys(keypool, Direction::Encrypt)?.encrypt_data_unit(data_unit, data)
+    }
+}
+
+// Primary and secondary cipher of every cipher of an encryption algorithm, keyed once for any number of data units.
+pub struct ExpandedKeys {
+    ciphers: Vec<(Box<dyn BlockCipher>, Box<dyn BlockCipher>)>,
+}
+
+impl ExpandedKeys {
+    // Needs keys expanded for Direction::Decrypt.
+    pub fn decrypt_data_unit(&self, data_unit: u64, data: &mut [u8]) -> Result<(), TcFinderError> {
+        for (cipher1, cipher2) in self.ciphers.iter().rev() {
+            xts::decrypt_data_unit(&**cipher1, &**cipher2, data_unit, data)?;
+        }
+        Ok(())
+    }
+
+    // Needs keys expanded for Direction::Encrypt.
+    pub fn encrypt_data_unit(&self, data_unit: u64, data: &mut [u8]) -> Result<(), TcFinderError> {
+        for (cipher1, cipher2) in &self.ciphers {
+            xts::encrypt_data_unit(&**cipher1, &**cipher2, data_unit, data)?;
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{Cipher, Direction, EncryptionAlgorithm, ALL_ALGORITHMS};
+
+    fn hex_str_to_vec(s: &str) -> Vec<u8> {
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i+2], 16).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_cascades() {
+        // Computed with the AES, Serpent, Twofish and Camellia of Nettle and a separate XTS implementation,
+        // keys taken from the key pool and ciphers applied in the order of the TrueCrypt algorithm definitions.
+        let keypool: Vec<u8> = (0..192).map(|i| i as u8).collect();
+        let plaintext: Vec<u8> = (0..64).map(|i| i as u8).collect();
+        let test_cases = [
+            (EncryptionAlgorithm::AesTwofishSerpent,
+             "903d3313f4d1a0d76ca792084f642b0cd737e428d730dd2208ad7ef17872463d\
+              4bd660c539379e02525ecc95625b42817f2364341f3a9e0aea00ae6e0bd5a0fc"),
+            (EncryptionAlgorithm::AesTwofish,
+             "0ab90c76b3294b0ff7e0b8d4a25acaecfe847599726796828cdb1b608c2efd1b\
+              7cc961bb605f17b80aec835544d7a01607f969add9f3a1d05d73c63049cb00c8"),
+            (EncryptionAlgorithm::SerpentAes,
+             "2ccd7082777821f74639fba45790e47953265c1987f288a8aef8177a3fd9baa1\
+              ed0b64ede03201a695b9c37d13420378bcee79e4f37774c32facc3336c277781"),
+            (EncryptionAlgorithm::SerpentTwofishAes,
+             "1b4aa17dcd59ec5a0cc737bae69cd47686d1d2a46333627e6ee13c358bcc668c\
+              49598171b06af874212505fece8be19a2b5bc5fd73af7fb218f855aefdb40a7d"),
+            (EncryptionAlgorithm::TwofishSerpent,
+             "d04c89be8d0e7c0137d68f8724f0de7bf14d93d471c1a5b2e689f5941e3c270b\
+              952450799b32c435fa01d23732bb44deda81c37ceb05243110016dbf5d3ebf9f"),
+            (EncryptionAlgorithm::CamelliaSerpent,
+             "4777027b33fad13239ae99f24bc3b98c88a3e1d9c5d4d18f08cb16e1eca36c53\
+              4926bf7b22daabeb93ff11634e63c9f952da5fcc2481626104dac90c1316b2a5"),
+        ];
+
+        for &(algorithm, ciphertext) in &test_cases {
+            let mut data = plaintext.clone();
+            algorithm.encrypt_data_unit(&keypool[..algorithm.key_size()], 5, &mut data).unwrap();
+            assert_eq!(data, hex_str_to_vec(ciphertext), "{:?}", algorithm);
+            algorithm.decrypt_data_unit(&keypool[..algorithm.key_size()], 5, &mut data).unwrap();
+            assert_eq!(data, plaintext, "{:?}", algorithm);
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let keypool: Vec<u8> = (0..192).map(|i| (i * 7) as u8).collect();
+        let plaintext: Vec<u8> = (0..448).map(|i| i as u8).collect();
+        for algorithm in &ALL_ALGORITHMS {
+            let mut data = plaintext.clone();
+            algorithm.encrypt_data_unit(&keypool, 0, &mut data).unwrap();
+            assert_ne!(data, plaintext, "{:?}", algorithm);
+            algorithm.decrypt_data_unit(&keypool, 0, &mut data).unwrap();
+            assert_eq!(data, plaintext, "{:?}", algorithm);
+        }
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        let keypool: Vec<u8> = (0..192).map(|i| i as u8).collect();
+        let mut data = [0u8; 48];
+        assert!(EncryptionAlgorithm::AesTwofishSerpent.expand_keys(&keypool[..64], Direction::Decrypt).is_err());
+        assert!(EncryptionAlgorithm::Aes.decrypt_data_unit(&keypool, 0, &mut data[..20]).is_err());
+        // AES expands the key only for the requested direction.
+        let keys = EncryptionAlgorithm::Aes.expand_keys(&keypool, Direction::Encrypt).unwrap();
+        assert!(keys.decrypt_data_unit(0, &mut data).is_err());
+        assert!(keys.encrypt_data_unit(0, &mut data).is_ok());
+
+        let aes = Cipher::Aes.new_block_cipher(&keypool[..32], Direction::Decrypt).unwrap();
+        assert!(aes.encrypt_block(&mut data[..16]).is_err());
+        assert!(aes.decrypt_block(&mut data[..15]).is_err());
+        assert!(Cipher::Serpent.new_block_cipher(&keypool[..16], Direction::Encrypt).is_err());
+    }
+
+    #[test]
+    fn test_names() {
+        for algorithm in &ALL_ALGORITHMS {
+            assert_eq!(EncryptionAlgorithm::from_name(algorithm.name()), Some(*algorithm));
+        }
+        assert_eq!(EncryptionAlgorithm::from_name("serpent-twofish-aes"), Some(EncryptionAlgorithm::SerpentTwofishAes));
+        assert_eq!(EncryptionAlgorithm::from_name("AES-Serpent"), None);
+
+        assert_eq!(super::parse_algorithms("All").unwrap(), ALL_ALGORITHMS);
+        assert_eq!(super::parse_algorithms("aes, Serpent-Twofish-AES").unwrap(),
+                   [EncryptionAlgorithm::Aes, EncryptionAlgorithm::SerpentTwofishAes]);
+        assert!(super::parse_algorithms("AES,AES-Serpent").is_err());
+    }
+}
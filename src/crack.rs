@@ -0,0 +1,169 @@
+// Password recovery for a header at a known offset: one salt, many passwords.
+
+use std::cmp;
+use std::fs::File;
+use std::ops::Range;
+use std::io::{Read, Seek, SeekFrom};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use pbr::ProgressBar;
+
+use cipher::EncryptionAlgorithm;
+use error::TcFinderError;
+use kdf::{self, KeyDerivation};
+use tcfinder::{self, FoundHeader};
+use volumeheader;
+
+// Candidates a thread takes at once.
+const BATCH_SIZE: u64 = 16;
+
+pub fn read_header(path: &str, offset: u64) -> Result<[u8; volumeheader::HEADER_SIZE], TcFinderError> {
+    let mut file = File::open(path).map_err(|e| TcFinderError::io(0, e))?;
+    file.seek(SeekFrom::Start(offset)).map_err(|e| TcFinderError::io(offset, e))?;
+    let mut header = [0u8; volumeheader::HEADER_SIZE];
+    file.read_exact(&mut header).map_err(|e| TcFinderError::io(offset, e))?;
+    Ok(header)
+}
+
+// Candidates of a keyspace of the given size after skipping the first skip, at most limit of them.
+// None if nothing is left to try.
+pub fn candidate_range(keyspace: u64, skip: u64, limit: Option<u64>) -> Option<Range<u64>> {
+    if skip >= keyspace {
+        return None;
+    }
+    let end = match limit {
+        Some(limit) => cmp::min(skip.saturating_add(limit), keyspace),
+        None => keyspace,
+    };
+    Some(skip..end)
+}
+
+// Reads the header at the byte offset of the file and tries the candidates with the indices on it, see crack.
+pub fn crack_file<F>(path: &str, offset: u64, derivations: &[KeyDerivation], algorithms: &[EncryptionAlgorithm],
+                     indices: Range<u64>, candidate: F) -> Result<Option<(u64, FoundHeader)>, TcFinderError>
+    where F: Fn(u64) -> Vec<u8> + Sync
+{
+    let header = read_header(path, offset)?;
+    Ok(crack(&header, derivations, algorithms, indices, candidate))
+}
+
+// Tries the candidates with the indices on the header with all cores. candidate(i) returns the password bytes
+// of candidate i, with keyfiles already applied.
+// Returns the index of the candidate that decrypted the header and the header. The sector is always 0.
+pub fn crack<F>(header: &[u8], derivations: &[KeyDerivation], algorithms: &[EncryptionAlgorithm], indices: Range<u64>,
+                candidate: F) -> Option<(u64, FoundHeader)>
+    where F: Fn(u64) -> Vec<u8> + Sync
+{
+    let threads = tcfinder::available_threads();
+    let next = AtomicU64::new(indices.start);
+    let done = AtomicBool::new(false);
+    let result = Mutex::new(None);
+
+    let mut progressbar = ProgressBar::new(indices.end.saturating_sub(indices.start));
+    progressbar.format("╢▌▌░╟");
+    let progressbar = Mutex::new(progressbar);
+
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| {
+                while !done.load(Ordering::Relaxed) {
+                    let start = next.fetch_add(BATCH_SIZE, Ordering::Relaxed);
+                    if start >= indices.end {
+                        break;
+                    }
+                    let end = cmp::min(start + BATCH_SIZE, indices.end);
+                    for index in start..end {
+                        if let Some((found, _)) = tcfinder::check_header(&kdf::password_keys(&[candidate(index)], derivations), algorithms, 0, header) {
+                            done.store(true, Ordering::Relaxed);
+                            *result.lock().unwrap() = Some((index, found));
+                            break;
+                        }
+                    }
+                    progressbar.lock().unwrap().add(end - start);
+                }
+            });
+        }
+    });
+    println!();
+
+    result.into_inner().unwrap()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use cipher::{EncryptionAlgorithm, ALL_ALGORITHMS};
+    use crc32;
+    use kdf::{KeyDerivation, Prf};
+    use volumeheader::{VolumeFormat, SALT_SIZE};
+
+    // TrueCrypt header with valid checksums, encrypted with the password.
+    fn encrypted_header(password: &[u8], derivation: &KeyDerivation, algorithm: EncryptionAlgorithm) -> Vec<u8> {
+        let mut decrypted = vec![0u8; 448];
+        decrypted[..4].copy_from_slice(b"TRUE");
+        decrypted[5] = 5;
+        for (i, byte) in decrypted[192..].iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        let key_area_crc = crc32::checksum(&decrypted[192..]);
+        decrypted[8..12].copy_from_slice(&key_area_crc.to_be_bytes());
+        let header_crc = crc32::checksum(&decrypted[..188]);
+        decrypted[188..192].copy_from_slice(&header_crc.to_be_bytes());
+
+        let salt = [7u8; SALT_SIZE];
+        let mut keypool = [0u8; 192];
+        derivation.derive_key(password, &salt, &mut keypool);
+        algorithm.encrypt_data_unit(&keypool, 0, &mut decrypted).unwrap();
+        salt.iter().chain(decrypted.iter()).cloned().collect()
+    }
+
+    #[test]
+    fn test_crack() {
+        let derivation = KeyDerivation { format: VolumeFormat::TrueCrypt, prf: Prf::Sha512, iterations: 10 };
+        let header = encrypted_header(b"secret", &derivation, EncryptionAlgorithm::TwofishSerpent);
+        let passwords: Vec<Vec<u8>> = (0..100).map(|i| format!("password{}", i).into_bytes())
+            .chain(Some(b"secret".to_vec())).collect();
+
+        let candidate = |i: u64| passwords[i as usize].clone();
+
+        let (index, found) = super::crack(&header, &[derivation], &ALL_ALGORITHMS, 0..passwords.len() as u64, candidate).unwrap();
+        assert_eq!(index, 100);
+        assert_eq!(found.algorithm, EncryptionAlgorithm::TwofishSerpent);
+        assert_eq!(found.header.version, 5);
+
+        assert_eq!(super::crack(&header, &[derivation], &ALL_ALGORITHMS, 90..101, candidate).unwrap().0, 100);
+        assert!(super::crack(&header, &[derivation], &ALL_ALGORITHMS, 0..100, candidate).is_none());
+        assert!(super::crack(&header, &[derivation], &[EncryptionAlgorithm::Aes], 0..101, candidate).is_none());
+    }
+
+    #[test]
+    fn test_candidate_range() {
+        assert_eq!(super::candidate_range(100, 0, None), Some(0..100));
+        assert_eq!(super::candidate_range(100, 10, Some(20)), Some(10..30));
+        assert_eq!(super::candidate_range(100, 90, Some(20)), Some(90..100));
+        assert_eq!(super::candidate_range(100, 10, Some(u64::MAX)), Some(10..100));
+        assert_eq!(super::candidate_range(100, 100, None), None);
+    }
+
+    #[test]
+    fn test_crack_file() {
+        let derivation = KeyDerivation { format: VolumeFormat::TrueCrypt, prf: Prf::Ripemd160, iterations: 10 };
+        let mut image = vec![0u8; 2048];
+        image[1024..1536].copy_from_slice(&encrypted_header(b"secret", &derivation, EncryptionAlgorithm::Aes));
+        let path = env::temp_dir().join("tcfinder_test_crack_file.img");
+        let path = path.to_str().unwrap();
+        fs::write(path, &image).unwrap();
+
+        let passwords = [b"password".to_vec(), b"secret".to_vec()];
+        let candidate = |i: u64| passwords[i as usize].clone();
+        let (index, _) = super::crack_file(path, 1024, &[derivation], &ALL_ALGORITHMS, 0..2, candidate).unwrap().unwrap();
+        assert_eq!(index, 1);
+        assert!(super::crack_file(path, 512, &[derivation], &ALL_ALGORITHMS, 0..2, candidate).unwrap().is_none());
+        assert!(super::crack_file(path, 1800, &[derivation], &ALL_ALGORITHMS, 0..2, candidate).is_err());
+        fs::remove_file(path).unwrap();
+    }
+}
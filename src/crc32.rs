@@ -0,0 +1,41 @@
+// CRC-32 (IEEE 802.3) as used by TrueCrypt for the header checksums.
+
+const POLYNOMIAL: u32 = 0xEDB8_8320;
+
+const TABLE: [u32; 256] = make_table();
+
+const fn make_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ POLYNOMIAL } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+pub fn checksum(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &b| update(crc, b))
+}
+
+// Single step without the initial and final inversion, used by the keyfile pool.
+pub fn update(crc: u32, byte: u8) -> u32 {
+    TABLE[((crc ^ u32::from(byte)) & 0xFF) as usize] ^ (crc >> 8)
+}
+
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_checksum() {
+        assert_eq!(super::checksum(b""), 0);
+        assert_eq!(super::checksum(b"123456789"), 0xCBF4_3926);
+        assert_eq!(super::checksum(b"The quick brown fox jumps over the lazy dog"), 0x414F_A339);
+    }
+}
@@ -0,0 +1,131 @@
+// GNU ddrescue mapfiles: the areas of a failing drive that were copied to the image. Only the finished ('+') blocks
+// hold data from the drive, non-tried, non-trimmed, non-scraped and bad areas are left out of the scan.
+
+use std::cmp;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+use error::TcFinderError;
+use partitioninfo::PartitionInfo;
+
+// Byte ranges [start, end) of the finished blocks, sorted and merged. Comment lines start with '#', the first
+// other line is the status line (current position and status), every following line a block: pos size status.
+pub fn read_mapfile(path: &str) -> Result<Vec<(u64, u64)>, TcFinderError> {
+    let file = File::open(path).map_err(|e| TcFinderError::file(path, e))?;
+    let mut lines = Vec::new();
+    for line in BufReader::new(file).lines() {
+        lines.push(line.map_err(|e| TcFinderError::file(path, e))?);
+    }
+    parse_mapfile(&lines).map_err(TcFinderError::Mapfile)
+}
+
+fn parse_mapfile<S: AsRef<str>>(lines: &[S]) -> Result<Vec<(u64, u64)>, &'static str> {
+    let mut lines = lines.iter().map(|line| line.as_ref().trim()).filter(|line| !line.is_empty() && !line.starts_with('#'));
+    lines.next().ok_or("Mapfile has no status line.")?;
+
+    let mut blocks: Vec<(u64, u64)> = Vec::new();
+    for line in lines {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 3 {
+            return Err("Invalid block line in mapfile.");
+        }
+        let pos = parse_number(fields[0])?;
+        let size = parse_number(fields[1])?;
+        match fields[2] {
+            "+" => (),
+            "?" | "*" | "/" | "-" => continue,
+            _ => return Err("Unknown block status in mapfile."),
+        }
+        let end = pos.checked_add(size).ok_or("Block in mapfile is too large.")?;
+        if size > 0 {
+            blocks.push((pos, end));
+        }
+    }
+
+    blocks.sort();
+    let mut merged: Vec<(u64, u64)> = Vec::new();
+    for (start, end) in blocks {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = cmp::max(last.1, end),
+            _ => merged.push((start, end)),
+        }
+    }
+    Ok(merged)
+}
+
+// ddrescue writes hexadecimal numbers with 0x, decimal and octal (leading 0) are accepted as well.
+fn parse_number(s: &str) -> Result<u64, &'static str> {
+    let result = if s.starts_with("0x") || s.starts_with("0X") {
+        u64::from_str_radix(&s[2..], 16)
+    } else if s.len() > 1 && s.starts_with('0') {
+        u64::from_str_radix(&s[1..], 8)
+    } else {
+        s.parse()
+    };
+    result.map_err(|_| "Invalid number in mapfile.")
+}
+
+// Inclusive sector ranges of the sectors lying completely in the blocks, up to last_sector.
+pub fn sector_ranges(blocks: &[(u64, u64)], sector_size: u64, last_sector: u64) -> Vec<(u64, u64)> {
+    let mut ranges = Vec::new();
+    for &(start, end) in blocks {
+        let first = start.div_ceil(sector_size);
+        let last = cmp::min(end / sector_size, last_sector + 1);
+        if first < last {
+            ranges.push((first, last - 1));
+        }
+    }
+    ranges
+}
+
+// Sector ranges of the finished blocks of the mapfile that lie inside the partition.
+pub fn mapfile_sector_ranges(path: &str, info: &PartitionInfo) -> Result<Vec<(u64, u64)>, TcFinderError> {
+    let blocks = read_mapfile(path)?;
+    let ranges = sector_ranges(&blocks, u64::from(info.bytes_per_sector), info.total_sectors_in_partition);
+    if ranges.is_empty() {
+        return Err(TcFinderError::Mapfile("The mapfile has no finished sectors."));
+    }
+    Ok(ranges)
+}
+
+
+#[cfg(test)]
+mod tests {
+    const MAPFILE: &str = "# Mapfile. Created by GNU ddrescue version 1.23
+# Command line: ddrescue /dev/sdb sdb.img sdb.map
+# current_pos  current_status  current_pass
+0x00C40000     +               1
+#      pos        size  status
+0x00000000  0x00010000  +
+0x00010000  0x00000400  -
+0x00010400  0x00000300  /
+0x00010700  0x00000100  +
+0x00010800  0x00100000  +
+0x00110800  0x00B30000  ?
+0x00C40800  0x00000200  *
+0x00C40A00  0x00010000  +
+";
+
+    #[test]
+    fn test_parse_mapfile() {
+        let lines: Vec<&str> = MAPFILE.lines().collect();
+        let blocks = super::parse_mapfile(&lines).unwrap();
+        assert_eq!(blocks, [(0, 0x10000), (0x10700, 0x110800), (0xC40A00, 0xC50A00)]);
+
+        assert_eq!(super::parse_mapfile(&["0 ?", "10 20 +", "30 040 +", "100 10 +"]).unwrap(), [(10, 62), (100, 110)]);
+        assert!(super::parse_mapfile::<&str>(&["# no status line"]).is_err());
+        assert!(super::parse_mapfile(&["0 ?", "0x10 +"]).is_err());
+        assert!(super::parse_mapfile(&["0 ?", "0x10 0x20 x"]).is_err());
+        assert!(super::parse_mapfile(&["0 ?", "0x10 0xZZ +"]).is_err());
+    }
+
+    #[test]
+    fn test_sector_ranges() {
+        let blocks = [(0, 0x10000), (0x10700, 0x110800), (0xC40A00, 0xC50A00)];
+        // The sector 0x10600-0x107FF is only partly rescued.
+        assert_eq!(super::sector_ranges(&blocks, 512, 100_000), [(0, 127), (132, 2179), (25093, 25220)]);
+        assert_eq!(super::sector_ranges(&blocks, 4096, 100_000), [(0, 15), (17, 271), (3137, 3151)]);
+        assert_eq!(super::sector_ranges(&blocks, 512, 1000), [(0, 127), (132, 1000)]);
+        assert!(super::sector_ranges(&[(100, 500)], 512, 1000).is_empty());
+    }
+}
@@ -0,0 +1,71 @@
+// Errors of the scanner, returned instead of panicking so a scan can be reported and recovered from.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum TcFinderError {
+    // Opening, seeking or reading the drive failed at the byte offset.
+    Io { offset: u64, error: io::Error },
+    // Opening or reading another file failed: wordlist, range list, mapfile, keyfile, rule or checkpoint file.
+    File { path: String, error: io::Error },
+    // The partition info could not be read from the first sector, e.g. because it is not NTFS.
+    UnsupportedFilesystem(&'static str),
+    // Sector range with start > end or beyond the end of the partition.
+    InvalidRange { start: u64, end: u64 },
+    // Line of a sector range list that is not 'start;end'.
+    InvalidRangeLine { line: usize },
+    // Headers could not be checked: no usable key derivation and encryption algorithm, or a worker failed.
+    Crypto(&'static str),
+    // The ddrescue mapfile could not be parsed or has no finished sectors.
+    Mapfile(&'static str),
+    // The checkpoint file could not be parsed or was saved for a different scan.
+    Checkpoint(&'static str),
+    // A keyfile argument is neither a file nor a directory, or a keyfile is empty.
+    Keyfile(&'static str),
+    // Rule that is not in the supported subset of the hashcat rule syntax.
+    Rule(&'static str),
+    // Invalid mask, custom charset or increment, or an index outside of the mask keyspace.
+    Mask(&'static str),
+    // Decrypted header with wrong size, magic bytes or checksums.
+    InvalidHeader(&'static str),
+}
+
+impl TcFinderError {
+    pub fn io(offset: u64, error: io::Error) -> TcFinderError {
+        TcFinderError::Io { offset, error }
+    }
+
+    pub fn file(path: &str, error: io::Error) -> TcFinderError {
+        TcFinderError::File { path: path.to_string(), error }
+    }
+}
+
+impl fmt::Display for TcFinderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TcFinderError::Io { offset, ref error } => write!(f, "I/O error at byte offset {}: {}", offset, error),
+            TcFinderError::File { ref path, ref error } => write!(f, "{}: {}", path, error),
+            TcFinderError::UnsupportedFilesystem(reason) => write!(f, "Unsupported filesystem: {}", reason),
+            TcFinderError::InvalidRange { start, end } => write!(f, "Invalid sector range {}-{}.", start, end),
+            TcFinderError::InvalidRangeLine { line } => write!(f, "Invalid sector range in line {}, expected 'start;end'.", line),
+            TcFinderError::Crypto(reason) => write!(f, "{}", reason),
+            TcFinderError::Mapfile(reason) => write!(f, "Mapfile: {}", reason),
+            TcFinderError::Checkpoint(reason) => write!(f, "Checkpoint: {}", reason),
+            TcFinderError::Keyfile(reason) => write!(f, "Keyfile: {}", reason),
+            TcFinderError::Rule(reason) => write!(f, "Rule: {}", reason),
+            TcFinderError::Mask(reason) => write!(f, "Mask: {}", reason),
+            TcFinderError::InvalidHeader(reason) => write!(f, "Invalid header: {}", reason),
+        }
+    }
+}
+
+impl Error for TcFinderError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            TcFinderError::Io { ref error, .. } | TcFinderError::File { ref error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
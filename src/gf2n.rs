@@ -25,29 +25,107 @@
 //
 // Original source: http://www.bjrn.se/code/pytruecrypt/gf2npy.txt
 
-#[repr(C, simd)]
-#[derive(Debug, Clone, Copy)]
-pub struct u64x2(pub u64, pub u64);
+// Multiplication in GF(2^128) modulo x^128 + x^7 + x^2 + x + 1, a and b are 16 byte big-endian numbers.
+// Uses PCLMULQDQ if the CPU supports it (detected at runtime), a portable carry-less multiplication otherwise.
+pub fn gfmul(a: &[u8; 16], b: &[u8; 16]) -> [u8; 16] {
+    let a = u128::from_be_bytes(*a);
+    let b = u128::from_be_bytes(*b);
 
-extern {
-    pub fn gfmul(a: u64x2, b: u64x2) -> u64x2;
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        if is_x86_feature_detected!("pclmulqdq") {
+            return unsafe { gfmul_pclmul(a, b) }.to_be_bytes();
+        }
+    }
+    gfmul_with(a, b, clmul_portable).to_be_bytes()
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "pclmulqdq")]
+unsafe fn gfmul_pclmul(a: u128, b: u128) -> u128 {
+    gfmul_with(a, b, |a, b| unsafe { clmul_pclmul(a, b) })
+}
+
+// https://software.intel.com/sites/default/files/managed/72/cc/clmul-wp-rev-2.02-2014-04-20.pdf
+// Performing Carry-less Multiplication of 128-bit Operands Using PCLMULQDQ. Algorithm 2 (Karatsuba),
+// with the 64 bit carry-less multiplication clmul.
+#[inline(always)]
+fn gfmul_with<F: Fn(u64, u64) -> u128>(a: u128, b: u128, clmul: F) -> u128 {
+    let (a0, a1) = (a as u64, (a >> 64) as u64);
+    let (b0, b1) = (b as u64, (b >> 64) as u64);
+    let c = clmul(a1, b1);
+    let d = clmul(a0, b0);
+    let e = clmul(a0 ^ a1, b0 ^ b1);
+    let (c0, c1) = (c as u64, (c >> 64) as u64);
+    let (d0, d1) = (d as u64, (d >> 64) as u64);
+    let (e0, e1) = (e as u64, (e >> 64) as u64);
+
+    let x3 = c1;
+    let x2 = c0 ^ c1 ^ d1 ^ e1;
+    let x1 = d1 ^ c0 ^ d0 ^ e0;
+    let x0 = d0;
+    reduce(x0, x1, x2, x3)
+}
+
+// Application of the method for reduction modulo x^128 + x^7 + x^2 + x + 1 (same paper).
+#[inline(always)]
+fn reduce(x0: u64, x1: u64, x2: u64, x3: u64) -> u128 {
+    let d = x2 ^ (x3 >> 63) ^ (x3 >> 62) ^ (x3 >> 57);
+
+    let e0 = d << 1;
+    let e1 = (x3 << 1) | (d >> 63);
+    let f0 = d << 2;
+    let f1 = (x3 << 2) | (d >> 62);
+    let g0 = d << 7;
+    let g1 = (x3 << 7) | (d >> 57);
+
+    let result0 = x0 ^ d ^ e0 ^ f0 ^ g0;
+    let result1 = x1 ^ x3 ^ e1 ^ f1 ^ g1;
+    (u128::from(result1) << 64) | u128::from(result0)
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "pclmulqdq")]
+unsafe fn clmul_pclmul(a: u64, b: u64) -> u128 {
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::*;
+
+    let product = _mm_clmulepi64_si128(_mm_set_epi64x(0, a as i64), _mm_set_epi64x(0, b as i64), 0);
+    let mut result = [0u8; 16];
+    _mm_storeu_si128(result.as_mut_ptr() as *mut __m128i, product);
+    u128::from_le_bytes(result)
+}
+
+// Carry-less multiplication of two 64 bit polynomials, bit by bit.
+fn clmul_portable(a: u64, b: u64) -> u128 {
+    let mut result = 0u128;
+    for i in 0..64 {
+        if (b >> i) & 1 == 1 {
+            result ^= u128::from(a) << i;
+        }
+    }
+    result
 }
 
-pub fn gfmul_simd(a: &[u8], b: &[u8]) -> Vec<u8> {
-    let a = &*a as *const _ as *const [u64; 2];
-    let b = &*b as *const _ as *const [u64; 2];
-    unsafe {
-        let a = u64x2((*a)[1].to_be(), (*a)[0].to_be());
-        let b = u64x2((*b)[1].to_be(), (*b)[0].to_be());
-        let res = gfmul(a, b);
-        let result_a = &res as *const _ as *const u64;
-        let r = [ (*result_a.offset(1)).to_be(), (*result_a).to_be() ];
-        (*(&r as *const _ as *const [u8; 16])).to_vec()
+// Multiplies the XTS tweak by the primitive element alpha (x) in GF(2^128).
+// Unlike gfmul, the tweak is interpreted as little-endian number as defined in IEEE 1619.
+pub fn mul_alpha(tweak: &mut [u8; 16]) {
+    let mut carry = 0u8;
+    for byte in tweak.iter_mut() {
+        let next_carry = *byte >> 7;
+        *byte = (*byte << 1) | carry;
+        carry = next_carry;
+    }
+    if carry == 1 {
+        tweak[0] ^= 0x87;
     }
 }
 
 #[cfg(test)]
 mod test {
+    #[cfg(feature = "nightly")]
     use test::Bencher;
 
     struct TestCase {
@@ -56,6 +134,12 @@ mod test {
         pub expected: Vec<u8>
     }
 
+    fn block(bytes: &[u8]) -> [u8; 16] {
+        let mut block = [0u8; 16];
+        block.copy_from_slice(bytes);
+        block
+    }
+
     fn hex_str_to_vec(s: &str) -> Vec<u8> {
         assert_eq!(s.len() % 2, 0);
         let mut v: Vec<u8> = Vec::new();
@@ -168,16 +252,55 @@ mod test {
         let test_cases = create_testcases();
 
         for test_case in &test_cases {
-            let result = super::gfmul_simd(&test_case.a, &test_case.b);
-            assert_eq!(&result, &test_case.expected);
+            let result = super::gfmul(&block(&test_case.a), &block(&test_case.b));
+            assert_eq!(&result[..], &test_case.expected[..]);
+        }
+    }
+
+    #[test]
+    fn portable_test() {
+        for test_case in &create_testcases() {
+            let a = u128::from_be_bytes(block(&test_case.a));
+            let b = u128::from_be_bytes(block(&test_case.b));
+            let result = super::gfmul_with(a, b, super::clmul_portable);
+            assert_eq!(result.to_be_bytes().to_vec(), test_case.expected);
+        }
+    }
+
+    #[test]
+    fn mul_alpha_test() {
+        let mut tweak = block(&hex_str_to_vec("01000000000000000000000000000000"));
+        super::mul_alpha(&mut tweak);
+        assert_eq!(tweak.to_vec(), hex_str_to_vec("02000000000000000000000000000000"));
+
+        let mut tweak = block(&hex_str_to_vec("80000000000000000000000000000000"));
+        super::mul_alpha(&mut tweak);
+        assert_eq!(tweak.to_vec(), hex_str_to_vec("00010000000000000000000000000000"));
+
+        let mut tweak = block(&hex_str_to_vec("00000000000000000000000000000080"));
+        super::mul_alpha(&mut tweak);
+        assert_eq!(tweak.to_vec(), hex_str_to_vec("87000000000000000000000000000000"));
+
+        // Multiplying by x, with the byte order reversed for gfmul.
+        let x = block(&hex_str_to_vec("00000000000000000000000000000002"));
+        for test_case in &create_testcases() {
+            let mut tweak = block(&test_case.a);
+            super::mul_alpha(&mut tweak);
+            let mut reversed = block(&test_case.a);
+            reversed.reverse();
+            let mut expected = super::gfmul(&reversed, &x);
+            expected.reverse();
+            assert_eq!(tweak, expected);
         }
     }
 
+    #[cfg(feature = "nightly")]
     #[bench]
     fn simd_test_bench(b: &mut Bencher) {
         let test_case = &create_testcases()[0];
+        let (a, x) = (block(&test_case.a), block(&test_case.b));
         b.iter(|| {
-            super::gfmul_simd(&test_case.a, &test_case.b);
+            super::gfmul(&a, &x);
         });
     }
     
@@ -0,0 +1,533 @@
+use std::mem;
+
+use crypto::digest::Digest;
+use crypto::ripemd160::Ripemd160;
+use crypto::sha2::{Sha256, Sha512};
+use crypto::whirlpool::Whirlpool;
+
+use multibuffer::{self, Lanes, Ripemd160Hmac, Sha512Hmac};
+use streebog::Streebog;
+use volumeheader::VolumeFormat;
+
+// Pseudorandom function (HMAC hash) used by PBKDF2 to derive the header key.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Prf {
+    Ripemd160,
+    Sha512,
+    Whirlpool,
+    // VeraCrypt only
+    Sha256,
+    // VeraCrypt only
+    Streebog,
+}
+
+impl Prf {
+    // Name as shown by TrueCrypt and VeraCrypt.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Prf::Ripemd160 => "RIPEMD-160",
+            Prf::Sha512 => "SHA-512",
+            Prf::Whirlpool => "Whirlpool",
+            Prf::Sha256 => "SHA-256",
+            Prf::Streebog => "Streebog",
+        }
+    }
+}
+
+pub const ALL_PRFS: [Prf; 5] = [Prf::Ripemd160, Prf::Sha512, Prf::Whirlpool, Prf::Sha256, Prf::Streebog];
+
+// TrueCrypt: PBKDF2-RIPEMD-160 with 2000 iterations, 1000 for system encryption and the other PRFs.
+const TRUECRYPT_RIPEMD160_ITERATIONS: u32 = 2000;
+const TRUECRYPT_ITERATIONS: u32 = 1000;
+// VeraCrypt: defaults if no PIM was chosen.
+const VERACRYPT_RIPEMD160_ITERATIONS: u32 = 655_331;
+const VERACRYPT_RIPEMD160_SYSTEM_ITERATIONS: u32 = 327_661;
+const VERACRYPT_ITERATIONS: u32 = 500_000;
+const VERACRYPT_SYSTEM_ITERATIONS: u32 = 200_000;
+
+// How the header key of one volume format is derived from the password.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyDerivation {
+    pub format: VolumeFormat,
+    pub prf: Prf,
+    pub iterations: u32,
+}
+
+impl KeyDerivation {
+    // PIM (Personal Iterations Multiplier) is VeraCrypt only, None or 0 selects the default iteration count.
+    // system: Volume is a system partition/drive encrypted with pre-boot authentication.
+    // Returns None if the format does not support the PRF or the iteration count of the PIM does not fit in a u32.
+    pub fn new(format: VolumeFormat, prf: Prf, pim: Option<u32>, system: bool) -> Option<KeyDerivation> {
+        let iterations = match format {
+            VolumeFormat::TrueCrypt => match prf {
+                Prf::Ripemd160 if !system => TRUECRYPT_RIPEMD160_ITERATIONS,
+                Prf::Ripemd160 | Prf::Sha512 | Prf::Whirlpool => TRUECRYPT_ITERATIONS,
+                Prf::Sha256 | Prf::Streebog => return None,
+            },
+            VolumeFormat::VeraCrypt => match pim {
+                Some(pim) if pim > 0 => {
+                    // VeraCrypt does not use the system encryption formula for SHA-512 and Whirlpool.
+                    if system && prf != Prf::Sha512 && prf != Prf::Whirlpool {
+                        pim.checked_mul(2048)?
+                    } else {
+                        pim.checked_mul(1000)?.checked_add(15_000)?
+                    }
+                }
+                _ => match prf {
+                    Prf::Ripemd160 if system => VERACRYPT_RIPEMD160_SYSTEM_ITERATIONS,
+                    Prf::Ripemd160 => VERACRYPT_RIPEMD160_ITERATIONS,
+                    Prf::Sha256 | Prf::Streebog if system => VERACRYPT_SYSTEM_ITERATIONS,
+                    _ => VERACRYPT_ITERATIONS,
+                },
+            },
+        };
+        Some(KeyDerivation { format, prf, iterations })
+    }
+
+    // Fills the key pool with PBKDF2 output for the given password and salt.
+    pub fn derive_key(&self, password: &[u8], salt: &[u8], keypool: &mut [u8]) {
+        PasswordKey::new(password, *self).derive_key(salt, keypool);
+    }
+}
+
+// A password prepared for one key derivation. The password stays the same for every header tried, so the HMAC
+// key blocks are hashed once here and only the salt dependent part of PBKDF2 is computed per header.
+#[derive(Clone)]
+pub struct PasswordKey {
+    pub derivation: KeyDerivation,
+    hmac: HmacKey,
+}
+
+// RIPEMD-160 and SHA-512 also have the key state for hashing several salts at once.
+#[derive(Clone)]
+enum HmacKey {
+    Ripemd160(HmacState<Ripemd160>, Ripemd160Hmac),
+    Sha512(HmacState<Sha512>, Sha512Hmac),
+    Whirlpool(HmacState<WhirlpoolDigest>),
+    Sha256(HmacState<Sha256>),
+    Streebog(HmacState<Streebog>),
+}
+
+impl PasswordKey {
+    pub fn new(password: &[u8], derivation: KeyDerivation) -> PasswordKey {
+        let hmac = match derivation.prf {
+            Prf::Ripemd160 => {
+                let block = key_block(&mut Ripemd160::new(), password);
+                HmacKey::Ripemd160(HmacState::with_key_block(Ripemd160::new(), &block), Ripemd160Hmac::new(&block))
+            }
+            Prf::Sha512 => {
+                let block = key_block(&mut Sha512::new(), password);
+                HmacKey::Sha512(HmacState::with_key_block(Sha512::new(), &block), Sha512Hmac::new(&block))
+            }
+            Prf::Whirlpool => HmacKey::Whirlpool(HmacState::new(WhirlpoolDigest(Whirlpool::new()), password)),
+            Prf::Sha256 => HmacKey::Sha256(HmacState::new(Sha256::new(), password)),
+            Prf::Streebog => HmacKey::Streebog(HmacState::new(Streebog::new(), password)),
+        };
+        PasswordKey { derivation, hmac }
+    }
+
+    // Fills the key pool with PBKDF2 output for the salt.
+    pub fn derive_key(&self, salt: &[u8], keypool: &mut [u8]) {
+        let mut block = [0u8; MAX_OUTPUT_SIZE];
+        let block = &mut block[..self.block_size()];
+        for (index, chunk) in keypool.chunks_mut(block.len()).enumerate() {
+            self.derive_block(salt, index, block);
+            chunk.copy_from_slice(&block[..chunk.len()]);
+        }
+    }
+
+    // Bytes of PBKDF2 output per block, the digest output size of the PRF.
+    pub fn block_size(&self) -> usize {
+        match self.hmac {
+            HmacKey::Ripemd160(ref hmac, _) => hmac.inner.output_bytes(),
+            HmacKey::Sha512(ref hmac, _) => hmac.inner.output_bytes(),
+            HmacKey::Whirlpool(ref hmac) => hmac.inner.output_bytes(),
+            HmacKey::Sha256(ref hmac) => hmac.inner.output_bytes(),
+            HmacKey::Streebog(ref hmac) => hmac.inner.output_bytes(),
+        }
+    }
+
+    // Derives the PBKDF2 output block with the index (starting at 0), out has the block size.
+    pub fn derive_block(&self, salt: &[u8], index: usize, out: &mut [u8]) {
+        self.derive_block_iterations(salt, index, self.derivation.iterations, out);
+    }
+
+    // First length bytes of the key pools of all salts. With RIPEMD-160 and SHA-512 the iterations of
+    // multibuffer::LANES salts run at once, the other PRFs derive one salt after the other.
+    pub fn derive_keys(&self, salts: &[&[u8]], length: usize) -> Vec<Vec<u8>> {
+        self.derive_keys_lanes(salts, length, Lanes::detect())
+    }
+
+    fn derive_keys_lanes(&self, salts: &[&[u8]], length: usize, lanes: Lanes) -> Vec<Vec<u8>> {
+        let block_size = self.block_size();
+        let block_count = length.div_ceil(block_size);
+        let mut keypools = vec![Vec::with_capacity(block_count * block_size); salts.len()];
+
+        for index in 0..block_count {
+            for (salts, keypools) in salts.chunks(multibuffer::LANES).zip(keypools.chunks_mut(multibuffer::LANES)) {
+                let iterations = self.derivation.iterations;
+                let blocks = match self.hmac {
+                    HmacKey::Ripemd160(_, ref batch) => batch.iterate(&self.derive_blocks(salts, index, 1), iterations, lanes),
+                    HmacKey::Sha512(_, ref batch) => batch.iterate(&self.derive_blocks(salts, index, 1), iterations, lanes),
+                    _ => self.derive_blocks(salts, index, iterations),
+                };
+                for (keypool, block) in keypools.iter_mut().zip(blocks) {
+                    keypool.extend_from_slice(&block);
+                }
+            }
+        }
+        for keypool in &mut keypools {
+            keypool.truncate(length);
+        }
+        keypools
+    }
+
+    // The block with the index of every salt, computed with the given number of iterations.
+    // A single iteration hashes the salt, the batched iterations after it only the previous output.
+    fn derive_blocks(&self, salts: &[&[u8]], index: usize, iterations: u32) -> Vec<Vec<u8>> {
+        salts.iter().map(|salt| {
+            let mut block = vec![0u8; self.block_size()];
+            self.derive_block_iterations(salt, index, iterations, &mut block);
+            block
+        }).collect()
+    }
+
+    fn derive_block_iterations(&self, salt: &[u8], index: usize, iterations: u32, out: &mut [u8]) {
+        match self.hmac {
+            HmacKey::Ripemd160(ref hmac, _) => pbkdf2_block(hmac, salt, iterations, index, out),
+            HmacKey::Sha512(ref hmac, _) => pbkdf2_block(hmac, salt, iterations, index, out),
+            HmacKey::Whirlpool(ref hmac) => pbkdf2_block(hmac, salt, iterations, index, out),
+            HmacKey::Sha256(ref hmac) => pbkdf2_block(hmac, salt, iterations, index, out),
+            HmacKey::Streebog(ref hmac) => pbkdf2_block(hmac, salt, iterations, index, out),
+        }
+    }
+}
+
+// Key pool of one password and salt, derived block by block when an encryption algorithm needs more of it.
+// Single ciphers only use the first 64 bytes, the rest is derived only if a cascade is tried.
+pub struct LazyKeypool<'a> {
+    key: &'a PasswordKey,
+    salt: &'a [u8],
+    keypool: Vec<u8>,
+}
+
+impl<'a> LazyKeypool<'a> {
+    pub fn new(key: &'a PasswordKey, salt: &'a [u8]) -> LazyKeypool<'a> {
+        LazyKeypool { key, salt, keypool: Vec::new() }
+    }
+
+    // First length bytes of the key pool.
+    pub fn get(&mut self, length: usize) -> &[u8] {
+        let block_size = self.key.block_size();
+        while self.keypool.len() < length {
+            let index = self.keypool.len() / block_size;
+            let start = self.keypool.len();
+            self.keypool.resize(start + block_size, 0);
+            self.key.derive_block(self.salt, index, &mut self.keypool[start..]);
+        }
+        &self.keypool[..length]
+    }
+}
+
+// Every password prepared for every key derivation, indexed [password][derivation].
+pub fn password_keys(passwords: &[Vec<u8>], derivations: &[KeyDerivation]) -> Vec<Vec<PasswordKey>> {
+    passwords.iter()
+        .map(|password| derivations.iter().map(|&derivation| PasswordKey::new(password, derivation)).collect())
+        .collect()
+}
+
+// Salts PasswordKey::derive_keys derives at once with RIPEMD-160 and SHA-512.
+pub const BATCH_SIZE: usize = multibuffer::LANES;
+
+// Largest digest output of the PRFs (SHA-512, Whirlpool, Streebog).
+const MAX_OUTPUT_SIZE: usize = 64;
+
+// HMAC with the key already hashed: digest states after the inner (key ^ ipad) and outer (key ^ opad) block.
+#[derive(Clone)]
+struct HmacState<D> {
+    inner: D,
+    outer: D,
+}
+
+impl<D: Digest + Clone> HmacState<D> {
+    fn new(mut digest: D, key: &[u8]) -> HmacState<D> {
+        let block = key_block(&mut digest, key);
+        HmacState::with_key_block(digest, &block)
+    }
+
+    // block is the key padded to the digest block size.
+    fn with_key_block(digest: D, block: &[u8]) -> HmacState<D> {
+        let mut inner = digest.clone();
+        inner.input(&block.iter().map(|b| b ^ 0x36).collect::<Vec<u8>>());
+        let mut outer = digest;
+        outer.input(&block.iter().map(|b| b ^ 0x5c).collect::<Vec<u8>>());
+        HmacState { inner, outer }
+    }
+
+    // HMAC of the concatenated data, out has the digest output size.
+    fn mac(&self, data: &[&[u8]], out: &mut [u8]) {
+        let mut inner = self.inner.clone();
+        for part in data {
+            inner.input(part);
+        }
+        let mut inner_hash = [0u8; MAX_OUTPUT_SIZE];
+        let inner_hash = &mut inner_hash[..out.len()];
+        inner.result(inner_hash);
+
+        let mut outer = self.outer.clone();
+        outer.input(inner_hash);
+        outer.result(out);
+    }
+}
+
+// HMAC key padded to the block size of the digest, keys longer than a block are hashed first.
+fn key_block<D: Digest>(digest: &mut D, key: &[u8]) -> Vec<u8> {
+    let mut block = vec![0u8; digest.block_size()];
+    if key.len() > block.len() {
+        digest.input(key);
+        digest.result(&mut block[..digest.output_bytes()]);
+        digest.reset();
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    block
+}
+
+// One output block of PBKDF2 (RFC 2898) with a prepared HMAC.
+fn pbkdf2_block<D: Digest + Clone>(hmac: &HmacState<D>, salt: &[u8], iterations: u32, index: usize, out: &mut [u8]) {
+    let size = out.len();
+    let mut u = [0u8; MAX_OUTPUT_SIZE];
+    let mut next = [0u8; MAX_OUTPUT_SIZE];
+
+    let block_index = (index as u32 + 1).to_be_bytes();
+    hmac.mac(&[salt, &block_index], &mut u[..size]);
+    out.copy_from_slice(&u[..size]);
+    for _ in 1..iterations {
+        hmac.mac(&[&u[..size]], &mut next[..size]);
+        mem::swap(&mut u, &mut next);
+        for (t, u) in out.iter_mut().zip(&u[..size]) {
+            *t ^= u;
+        }
+    }
+}
+
+// rust-crypto's Whirlpool reports its block size in bits (512) instead of bytes, which breaks HMAC.
+#[derive(Clone)]
+struct WhirlpoolDigest(Whirlpool);
+
+impl Digest for WhirlpoolDigest {
+    fn input(&mut self, input: &[u8]) {
+        self.0.input(input)
+    }
+
+    fn result(&mut self, out: &mut [u8]) {
+        self.0.result(out)
+    }
+
+    fn reset(&mut self) {
+        self.0.reset()
+    }
+
+    fn output_bits(&self) -> usize {
+        self.0.output_bits()
+    }
+
+    fn block_size(&self) -> usize {
+        64
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{HmacKey, KeyDerivation, Prf};
+    use multibuffer::Lanes;
+    #[cfg(feature = "nightly")]
+    use test::Bencher;
+    use volumeheader::VolumeFormat;
+
+    fn iterations(format: VolumeFormat, prf: Prf, pim: Option<u32>, system: bool) -> Option<u32> {
+        KeyDerivation::new(format, prf, pim, system).map(|derivation| derivation.iterations)
+    }
+
+    #[test]
+    fn test_iterations() {
+        use self::Prf::*;
+        use volumeheader::VolumeFormat::*;
+
+        assert_eq!(iterations(TrueCrypt, Ripemd160, None, false), Some(2000));
+        assert_eq!(iterations(TrueCrypt, Ripemd160, Some(10), false), Some(2000));
+        assert_eq!(iterations(TrueCrypt, Ripemd160, None, true), Some(1000));
+        assert_eq!(iterations(TrueCrypt, Sha512, None, false), Some(1000));
+        assert_eq!(iterations(TrueCrypt, Whirlpool, None, false), Some(1000));
+        assert_eq!(iterations(TrueCrypt, Sha256, None, false), None);
+        assert_eq!(iterations(TrueCrypt, Streebog, None, false), None);
+
+        assert_eq!(iterations(VeraCrypt, Ripemd160, None, false), Some(655_331));
+        assert_eq!(iterations(VeraCrypt, Ripemd160, Some(0), false), Some(655_331));
+        assert_eq!(iterations(VeraCrypt, Ripemd160, None, true), Some(327_661));
+        assert_eq!(iterations(VeraCrypt, Ripemd160, Some(1), false), Some(16_000));
+        assert_eq!(iterations(VeraCrypt, Ripemd160, Some(98), true), Some(200_704));
+        assert_eq!(iterations(VeraCrypt, Sha512, None, false), Some(500_000));
+        assert_eq!(iterations(VeraCrypt, Whirlpool, None, false), Some(500_000));
+        assert_eq!(iterations(VeraCrypt, Sha256, None, false), Some(500_000));
+        assert_eq!(iterations(VeraCrypt, Sha256, None, true), Some(200_000));
+        assert_eq!(iterations(VeraCrypt, Streebog, None, false), Some(500_000));
+        assert_eq!(iterations(VeraCrypt, Streebog, Some(485), false), Some(500_000));
+        assert_eq!(iterations(VeraCrypt, Sha512, Some(98), true), Some(113_000));
+        assert_eq!(iterations(VeraCrypt, Sha512, Some(u32::MAX), false), None);
+        assert_eq!(iterations(VeraCrypt, Ripemd160, Some(u32::MAX), true), None);
+        assert_eq!(iterations(VeraCrypt, Sha512, Some(4_294_952), false), Some(4_294_967_000));
+        assert_eq!(iterations(VeraCrypt, Sha512, Some(4_294_953), false), None);
+    }
+
+    fn hex_str_to_vec(s: &str) -> Vec<u8> {
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i+2], 16).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_derive_key() {
+        let test_cases = [
+            (Prf::Ripemd160, "195f4b40815febc70f83b6fbccf5d76f9280ec5ff767060bbb95ac032f3bd554\
+                              91734558f3a01fb245e067929de5b4bccce2b3427e5a11ad582f0cbd54bb34c9"),
+            (Prf::Sha512, "2b75a60bec72b9f9613d9c03198d7349b95c5624df8f3866a6a8b9acdfc0a2de\
+                           eb6d0cd577665737822825fd2b978c798a0bcf3c959d12f0ca1ba819411c70b3"),
+            (Prf::Whirlpool, "08d4e76e884ccbf28c0cc0cb2cc4c41b165b278e2abed6c6a8982350478a0653\
+                              b6ccc153d23f5f1ce9a86a75a64966ffd7876700cc6c03288ab61ca32908b39c"),
+            (Prf::Sha256, "5f0911774eab5f75fa232c64e1aa49654ab30104f6fd052537031223217e76cc\
+                           99c948c54af8d39cd96d754a533f07e1d06a4afbab74f8f1f42234f9f71e5d18"),
+            (Prf::Streebog, "3cb302150e65ccd805848d48f29cb7fd3399859ee71217b97025c2a4ea475a47\
+                             df43fda99ff54da1924493fb720cbab9a251702ebb2454d0e8f3211915031e56"),
+        ];
+
+        for &(prf, expected) in &test_cases {
+            let derivation = KeyDerivation { format: VolumeFormat::VeraCrypt, prf, iterations: 10 };
+            let mut keypool = [0u8; 64];
+            derivation.derive_key(b"p4ssword", b"salt", &mut keypool);
+            assert_eq!(keypool.to_vec(), hex_str_to_vec(expected), "{:?}", prf);
+        }
+    }
+
+    #[test]
+    fn test_long_password() {
+        use crypto::hmac::Hmac;
+        use crypto::pbkdf2::pbkdf2;
+        use crypto::ripemd160::Ripemd160;
+        use crypto::sha2::Sha512;
+
+        // Keys longer than the hash block size are hashed before use.
+        let password = [0x61u8; 200];
+        let salt = [0x73u8; 64];
+        let mut expected = [0u8; 192];
+        let mut keypool = [0u8; 192];
+
+        pbkdf2(&mut Hmac::new(Ripemd160::new(), &password), &salt, 5, &mut expected);
+        let derivation = KeyDerivation { format: VolumeFormat::TrueCrypt, prf: Prf::Ripemd160, iterations: 5 };
+        derivation.derive_key(&password, &salt, &mut keypool);
+        assert_eq!(keypool.to_vec(), expected.to_vec());
+
+        pbkdf2(&mut Hmac::new(Sha512::new(), &password), &salt, 5, &mut expected);
+        let key = super::PasswordKey::new(&password, KeyDerivation { prf: Prf::Sha512, ..derivation });
+        key.derive_key(&salt, &mut keypool);
+        assert_eq!(keypool.to_vec(), expected.to_vec());
+    }
+
+    #[test]
+    fn test_derive_keys() {
+        // More salts than lanes, the last batch is not full.
+        let salts: Vec<Vec<u8>> = (0..11).map(|i| vec![i as u8; 64]).collect();
+        let salts: Vec<&[u8]> = salts.iter().map(|salt| &salt[..]).collect();
+        for password in &[b"p4ssword".to_vec(), vec![0x61u8; 200]] {
+            for &prf in &super::ALL_PRFS {
+                let key = super::PasswordKey::new(password, KeyDerivation { format: VolumeFormat::VeraCrypt, prf, iterations: 7 });
+                let keypools = key.derive_keys(&salts, 192);
+                assert_eq!(keypools.len(), salts.len());
+                for (keypool, salt) in keypools.iter().zip(&salts) {
+                    let mut expected = [0u8; 192];
+                    key.derive_key(salt, &mut expected);
+                    assert_eq!(keypool[..], expected[..], "{:?}", prf);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_derive_keys_lanes() {
+        // The multi-buffer iterations with the scalar and the AVX2 lanes against the single salt PBKDF2.
+        let mut all_lanes = vec![Lanes::Scalar];
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        {
+            if is_x86_feature_detected!("avx2") {
+                all_lanes.push(Lanes::Avx2);
+            }
+        }
+        let salts: Vec<Vec<u8>> = (0..11).map(|i| vec![i as u8 * 3; 64]).collect();
+        let salts: Vec<&[u8]> = salts.iter().map(|salt| &salt[..]).collect();
+        for &lanes in &all_lanes {
+            for password in &[b"p4ssword".to_vec(), vec![0x61u8; 200]] {
+                for &prf in &[Prf::Ripemd160, Prf::Sha512] {
+                    let key = super::PasswordKey::new(password, KeyDerivation { format: VolumeFormat::VeraCrypt, prf, iterations: 9 });
+                    let keypools = key.derive_keys_lanes(&salts, 192, lanes);
+                    for (keypool, salt) in keypools.iter().zip(&salts) {
+                        for (index, block) in keypool.chunks(key.block_size()).enumerate() {
+                            let mut expected = vec![0u8; key.block_size()];
+                            match key.hmac {
+                                HmacKey::Ripemd160(ref hmac, _) => super::pbkdf2_block(hmac, salt, 9, index, &mut expected),
+                                HmacKey::Sha512(ref hmac, _) => super::pbkdf2_block(hmac, salt, 9, index, &mut expected),
+                                _ => unreachable!(),
+                            }
+                            assert_eq!(block, &expected[..block.len()], "{:?} {:?}", prf, lanes);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_lazy_keypool() {
+        let salt = [0x73u8; 64];
+        for &prf in &super::ALL_PRFS {
+            let key = super::PasswordKey::new(b"p4ssword", KeyDerivation { format: VolumeFormat::VeraCrypt, prf, iterations: 3 });
+            let mut expected = [0u8; 192];
+            key.derive_key(&salt, &mut expected);
+
+            let mut keypool = super::LazyKeypool::new(&key, &salt);
+            assert_eq!(keypool.get(64), &expected[..64], "{:?}", prf);
+            assert_eq!(keypool.get(192), &expected[..], "{:?}", prf);
+            assert_eq!(keypool.get(128), &expected[..128], "{:?}", prf);
+        }
+    }
+
+    // One batch of salts with the TrueCrypt default key derivation, the batched multi-buffer derivation
+    // against one scalar PBKDF2 per salt. Both derive the key pool for all ciphers of a cascade.
+    #[cfg(feature = "nightly")]
+    fn bench_salts() -> Vec<Vec<u8>> {
+        (0..super::BATCH_SIZE).map(|i| vec![i as u8; 64]).collect()
+    }
+
+    #[cfg(feature = "nightly")]
+    #[bench]
+    fn test_derive_keys_bench(b: &mut Bencher) {
+        let derivation = KeyDerivation::new(VolumeFormat::TrueCrypt, Prf::Ripemd160, None, false).unwrap();
+        let keys = super::password_keys(&[b"p4ssword".to_vec()], &[derivation]);
+        let salts = bench_salts();
+        let salts: Vec<&[u8]> = salts.iter().map(|salt| &salt[..]).collect();
+        b.iter(|| keys[0][0].derive_keys(&salts, 192));
+    }
+
+    #[cfg(feature = "nightly")]
+    #[bench]
+    fn test_derive_key_scalar_bench(b: &mut Bencher) {
+        let derivation = KeyDerivation::new(VolumeFormat::TrueCrypt, Prf::Ripemd160, None, false).unwrap();
+        let keys = super::password_keys(&[b"p4ssword".to_vec()], &[derivation]);
+        let salts = bench_salts();
+        b.iter(|| {
+            salts.iter().map(|salt| {
+                let mut keypool = vec![0u8; 192];
+                keys[0][0].derive_key(salt, &mut keypool);
+                keypool
+            }).collect::<Vec<_>>()
+        });
+    }
+}
@@ -0,0 +1,134 @@
+// Keyfiles as applied by TrueCrypt and VeraCrypt: every keyfile is hashed into a pool with a running CRC-32,
+// the pool is then added to the password bytes before the header key derivation.
+
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crc32;
+use error::TcFinderError;
+
+const POOL_SIZE: usize = 64;
+// VeraCrypt uses a larger pool for passwords longer than the TrueCrypt maximum of 64 bytes.
+const POOL_SIZE_LONG_PASSWORD: usize = 128;
+// Only the first 1 MiB of a keyfile is used.
+const MAX_READ_LENGTH: u64 = 1024 * 1024;
+
+// Expands the keyfile arguments. For a directory all files directly in it are used,
+// except hidden files (name starting with '.'). Subdirectories are not searched.
+pub fn collect_keyfiles(paths: &[String]) -> Result<Vec<PathBuf>, TcFinderError> {
+    let mut keyfiles = Vec::new();
+    for name in paths {
+        let path = Path::new(name);
+        let metadata = fs::metadata(path).map_err(|e| TcFinderError::file(name, e))?;
+        if metadata.is_dir() {
+            let entries = fs::read_dir(path).map_err(|e| TcFinderError::file(name, e))?;
+            let mut files = Vec::new();
+            for entry in entries {
+                let entry = entry.map_err(|e| TcFinderError::file(name, e))?;
+                let hidden = entry.file_name().to_string_lossy().starts_with('.');
+                if !hidden && entry.path().is_file() {
+                    files.push(entry.path());
+                }
+            }
+            files.sort();
+            keyfiles.extend(files);
+        } else if metadata.is_file() {
+            keyfiles.push(path.to_path_buf());
+        } else {
+            return Err(TcFinderError::Keyfile("Keyfile is neither a file nor a directory."));
+        }
+    }
+    Ok(keyfiles)
+}
+
+// Keyfile pools of both sizes, read once and applied to every password.
+pub struct KeyfilePool {
+    pool: Vec<u8>,
+    long_password_pool: Vec<u8>,
+}
+
+impl KeyfilePool {
+    pub fn new(keyfiles: &[PathBuf]) -> Result<KeyfilePool, TcFinderError> {
+        let mut keyfile_pool = KeyfilePool { pool: Vec::new(), long_password_pool: Vec::new() };
+        if keyfiles.is_empty() {
+            return Ok(keyfile_pool);
+        }
+
+        keyfile_pool.pool = vec![0u8; POOL_SIZE];
+        keyfile_pool.long_password_pool = vec![0u8; POOL_SIZE_LONG_PASSWORD];
+        for path in keyfiles {
+            let file = File::open(path).map_err(|e| TcFinderError::file(&path.to_string_lossy(), e))?;
+            let mut data = Vec::new();
+            file.take(MAX_READ_LENGTH).read_to_end(&mut data).map_err(|e| TcFinderError::file(&path.to_string_lossy(), e))?;
+            if data.is_empty() {
+                return Err(TcFinderError::Keyfile("Keyfile is empty."));
+            }
+            add_to_pool(&mut keyfile_pool.pool, &data);
+            add_to_pool(&mut keyfile_pool.long_password_pool, &data);
+        }
+        Ok(keyfile_pool)
+    }
+
+    // Returns the password bytes with the keyfiles applied.
+    pub fn apply(&self, password: &[u8]) -> Vec<u8> {
+        if self.pool.is_empty() {
+            password.to_vec()
+        } else if password.len() <= POOL_SIZE {
+            apply_pool(password, &self.pool)
+        } else {
+            apply_pool(password, &self.long_password_pool)
+        }
+    }
+}
+
+// Adds the 4 bytes of the CRC after every keyfile byte to the pool, wrapping around at the end of the pool.
+fn add_to_pool(pool: &mut [u8], data: &[u8]) {
+    let mut crc = !0u32;
+    let mut position = 0;
+    for &byte in data {
+        crc = crc32::update(crc, byte);
+        for &crc_byte in &crc.to_be_bytes() {
+            pool[position] = pool[position].wrapping_add(crc_byte);
+            position = (position + 1) % pool.len();
+        }
+    }
+}
+
+// The pool is added to the password bytes, beyond the end of the password it replaces it.
+fn apply_pool(password: &[u8], pool: &[u8]) -> Vec<u8> {
+    let mut result = pool.to_vec();
+    for (byte, &password_byte) in result.iter_mut().zip(password) {
+        *byte = byte.wrapping_add(password_byte);
+    }
+    result
+}
+
+
+#[cfg(test)]
+mod tests {
+    fn hex_str_to_vec(s: &str) -> Vec<u8> {
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i+2], 16).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_keyfile_pool() {
+        let mut pool = [0u8; super::POOL_SIZE];
+        super::add_to_pool(&mut pool, b"keyfile");
+        assert_eq!(pool.to_vec(), hex_str_to_vec(
+            "f79da8a205f7cabb756f545626ac5ff8872e0f8dd78afce1ea02cdc500000000\
+             0000000000000000000000000000000000000000000000000000000000000000"));
+
+        // A second keyfile is added onto the same pool.
+        let data: Vec<u8> = (0..100).map(|i| i as u8).collect();
+        super::add_to_pool(&mut pool, &data);
+        assert_eq!(pool.to_vec(), hex_str_to_vec(
+            "6cb82864820113f2e1c2e438229cc4ac28a7bd7b799bce99e39649d05fbfa925\
+             5cafc37c2434e500b95c77bd2fa0bf53534fc782ffaa29a157981bf46bea7598"));
+
+        let password = super::apply_pool(b"p4ssword", &pool);
+        assert_eq!(password, hex_str_to_vec(
+            "dcec9bd7f9708556e1c2e438229cc4ac28a7bd7b799bce99e39649d05fbfa925\
+             5cafc37c2434e500b95c77bd2fa0bf53534fc782ffaa29a157981bf46bea7598"));
+    }
+}
@@ -0,0 +1,158 @@
+// Kuznyechik block cipher (GOST R 34.12-2015). Blocks and keys are in the byte order of the standard's
+// test vectors, the first byte is the most significant one.
+
+use cipher::{self, BlockCipher};
+use error::TcFinderError;
+use streebog::PI;
+
+const ROUNDS: usize = 10;
+
+const PI_INV: [u8; 256] = invert_pi();
+
+// Coefficients of the linear function l over GF(2^8), reduced by x^8 + x^7 + x^6 + x + 1.
+const L_VECTOR: [u8; 16] = [148, 32, 133, 16, 194, 192, 1, 251, 1, 192, 194, 16, 133, 32, 148, 1];
+const POLYNOMIAL: u16 = 0x1C3;
+
+const fn invert_pi() -> [u8; 256] {
+    let mut inv = [0u8; 256];
+    let mut i = 0;
+    while i < 256 {
+        inv[PI[i] as usize] = i as u8;
+        i += 1;
+    }
+    inv
+}
+
+pub struct Kuznyechik {
+    round_keys: [[u8; 16]; ROUNDS],
+}
+
+impl Kuznyechik {
+    pub fn new(key: &[u8]) -> Kuznyechik {
+        assert!(key.len() == 32, "Kuznyechik: key length != 32");
+        let mut round_keys = [[0u8; 16]; ROUNDS];
+        round_keys[0].copy_from_slice(&key[..16]);
+        round_keys[1].copy_from_slice(&key[16..]);
+
+        // Each pair of round keys is derived from the previous pair by 8 Feistel rounds with constants C_i = L(i).
+        for pair in 1..ROUNDS / 2 {
+            let (mut a1, mut a0) = (round_keys[2 * pair - 2], round_keys[2 * pair - 1]);
+            for i in 0..8 {
+                let mut c = [0u8; 16];
+                c[15] = (8 * (pair - 1) + i + 1) as u8;
+                linear(&mut c);
+
+                let mut t = a1;
+                xor(&mut t, &c);
+                substitute(&mut t, &PI);
+                linear(&mut t);
+                xor(&mut t, &a0);
+                a0 = a1;
+                a1 = t;
+            }
+            round_keys[2 * pair] = a1;
+            round_keys[2 * pair + 1] = a0;
+        }
+        Kuznyechik { round_keys }
+    }
+}
+
+impl BlockCipher for Kuznyechik {
+    fn encrypt_block(&self, block: &mut [u8]) -> Result<(), TcFinderError> {
+        cipher::check_block(block)?;
+        for round_key in &self.round_keys[..ROUNDS - 1] {
+            xor(block, round_key);
+            substitute(block, &PI);
+            linear(block);
+        }
+        xor(block, &self.round_keys[ROUNDS - 1]);
+        Ok(())
+    }
+
+    fn decrypt_block(&self, block: &mut [u8]) -> Result<(), TcFinderError> {
+        cipher::check_block(block)?;
+        xor(block, &self.round_keys[ROUNDS - 1]);
+        for round_key in self.round_keys[..ROUNDS - 1].iter().rev() {
+            linear_inv(block);
+            substitute(block, &PI_INV);
+            xor(block, round_key);
+        }
+        Ok(())
+    }
+}
+
+fn xor(block: &mut [u8], key: &[u8; 16]) {
+    for (b, k) in block.iter_mut().zip(key.iter()) {
+        *b ^= k;
+    }
+}
+
+fn substitute(block: &mut [u8], table: &[u8; 256]) {
+    for b in block.iter_mut() {
+        *b = table[*b as usize];
+    }
+}
+
+// L = R^16, R shifts the block by one byte and inserts l(block) in front.
+fn linear(block: &mut [u8]) {
+    for _ in 0..16 {
+        let l = l_function(block);
+        for i in (1..16).rev() {
+            block[i] = block[i - 1];
+        }
+        block[0] = l;
+    }
+}
+
+fn linear_inv(block: &mut [u8]) {
+    for _ in 0..16 {
+        let first = block[0];
+        for i in 0..15 {
+            block[i] = block[i + 1];
+        }
+        block[15] = first;
+        // l of the shifted block, with the dropped first byte in the last position.
+        block[15] = l_function(block);
+    }
+}
+
+fn l_function(block: &[u8]) -> u8 {
+    block.iter().zip(L_VECTOR.iter()).fold(0, |acc, (&b, &c)| acc ^ gf_multiply(b, c))
+}
+
+fn gf_multiply(mut a: u8, mut b: u8) -> u8 {
+    let mut result = 0u8;
+    while b != 0 {
+        if b & 1 == 1 {
+            result ^= a;
+        }
+        a = if a & 0x80 != 0 { ((u16::from(a) << 1) ^ POLYNOMIAL) as u8 } else { a << 1 };
+        b >>= 1;
+    }
+    result
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::Kuznyechik;
+    use cipher::BlockCipher;
+
+    fn hex_str_to_vec(s: &str) -> Vec<u8> {
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i+2], 16).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_kuznyechik() {
+        // GOST R 34.12-2015, appendix A.1
+        let key = hex_str_to_vec("8899aabbccddeeff0011223344556677fedcba98765432100123456789abcdef");
+        let plain = hex_str_to_vec("1122334455667700ffeeddccbbaa9988");
+        let kuznyechik = Kuznyechik::new(&key);
+
+        let mut block = plain.clone();
+        kuznyechik.encrypt_block(&mut block).unwrap();
+        assert_eq!(block, hex_str_to_vec("7f679d90bebc24305a468d42b9d4edcd"));
+        kuznyechik.decrypt_block(&mut block).unwrap();
+        assert_eq!(block, plain);
+    }
+}
@@ -0,0 +1,49 @@
+// Finds TrueCrypt and VeraCrypt volume headers on a drive or image, for recovering volumes whose partition
+// table or header location was lost. The tcfinder binary is a command line interface over this library.
+
+#![cfg_attr(all(feature = "nightly", test), feature(test))]
+
+extern crate crypto;
+#[cfg(unix)]
+extern crate libc;
+extern crate num;
+extern crate pbr;
+#[cfg(all(feature = "nightly", test))]
+extern crate test;
+extern crate threadpool;
+extern crate time;
+
+mod aes;
+mod camellia;
+pub mod checkpoint;
+pub mod cipher;
+pub mod crack;
+mod crc32;
+pub mod ddrescue;
+pub mod error;
+pub mod gf2n;
+pub mod kdf;
+pub mod keyfile;
+mod kuznyechik;
+pub mod mask;
+mod multibuffer;
+pub mod mutation;
+pub mod partitioninfo;
+pub mod ranges;
+pub mod reader;
+mod serpent;
+mod streebog;
+pub mod tcfinder;
+mod twofish;
+pub mod volumeheader;
+pub mod wordlist;
+mod xts;
+
+pub use checkpoint::Checkpoint;
+pub use cipher::EncryptionAlgorithm;
+pub use error::TcFinderError;
+pub use kdf::{KeyDerivation, Prf};
+pub use partitioninfo::PartitionInfo;
+pub use reader::ReaderKind;
+pub use tcfinder::{FoundHeader, ScanConfig, ScanResult, TCFinder};
+pub use volumeheader::{VolumeFormat, VolumeHeader};
@@ -1,29 +1,36 @@
-#![feature(plugin, test, simd_ffi, repr_simd)]
-
-extern crate crypto;
 extern crate docopt;
-extern crate num;
-extern crate pbr;
-extern crate rustc_serialize;
-extern crate test;
-extern crate threadpool;
-extern crate time;
 extern crate serde;
+extern crate tcfinder;
 
-mod aes;
-mod gf2n;
-mod partitioninfo;
-mod tcfinder;
-mod xts;
+use std::error::Error;
+use std::process;
 
 use docopt::Docopt;
-use tcfinder::TCFinder;
 use serde::Deserialize;
+use tcfinder::cipher::{self, EncryptionAlgorithm};
+use tcfinder::crack;
+use tcfinder::ddrescue;
+use tcfinder::kdf::{self, KeyDerivation, Prf};
+use tcfinder::keyfile::{self, KeyfilePool};
+use tcfinder::mask::Mask;
+use tcfinder::mutation::{self, Candidate};
+use tcfinder::ranges;
+use tcfinder::reader::ReaderKind;
+use tcfinder::tcfinder::{available_threads, FoundHeader, ScanConfig, TCFinder};
+use tcfinder::volumeheader::VolumeFormat;
+use tcfinder::wordlist;
 
 const USAGE: &str = "
-TrueCrypt Volume Header Finder.
+TrueCrypt and VeraCrypt Volume Header Finder.
+
+Scan: finds the sectors of TrueCrypt and VeraCrypt volume headers in the given sector ranges
+(or at the backup header positions with --backup) by trying to decrypt every sector with the passwords.
+Crack: the header location is already known, the header at the given offset is tried with the
+password, every password of the wordlist or every candidate of the mask, using all cores.
 
-Finds sector of TrueCrypt Volume Header if created with default config.
+Both modes try the selected volume formats (TrueCrypt, VeraCrypt), hash algorithms of the key
+derivation (RIPEMD-160, SHA-512, Whirlpool, SHA-256, Streebog) and encryption algorithms including
+the cascades, with the PIM, system encryption and keyfiles applied to every password.
 
 Run as admin!
 
@@ -32,65 +39,302 @@ or normal path to a file.
 
 
 Usage:
-tcfinder <path> <password> (<start> <end> | --ranges=<file>)
+tcfinder crack <path> <password> [--offset=<bytes>] [options] [--keyfile=<path>...]
+tcfinder crack <path> --wordlist=<file> [--offset=<bytes>] [options] [--keyfile=<path>...]
+tcfinder crack <path> --mask=<mask> [--offset=<bytes>] [options] [--keyfile=<path>...]
+tcfinder <path> <password> (<start> <end> | --ranges=<file> | --mapfile=<file>) [options] [--backup] [--volume-end=<sector>...] [--keyfile=<path>...]
+tcfinder <path> --wordlist=<file> (<start> <end> | --ranges=<file> | --mapfile=<file>) [options] [--backup] [--volume-end=<sector>...] [--keyfile=<path>...]
+tcfinder <path> <password> --backup [options] [--volume-end=<sector>...] [--keyfile=<path>...]
+tcfinder <path> --wordlist=<file> --backup [options] [--volume-end=<sector>...] [--keyfile=<path>...]
 tcfinder (-h | --help)
 
 Options:
-  -h, --help           Show this screen.
-  --ranges=<file>      Text file with sector ranges. Format: 'start;end'. Every sector range on new line.
+  -h, --help               Show this screen.
+  --offset=<bytes>         Byte offset of the header for crack. [default: 0]
+  --mask=<mask>            Hashcat style mask for crack: ?l ?u ?d ?h ?H ?s ?a ?b, ?1 to ?4 for the custom charsets,
+                           ?? for '?', other characters stand for themselves.
+  --charset1=<chars>       Custom charset ?1, may contain built-in charsets (e.g. '?l?d_').
+  --charset2=<chars>       Custom charset ?2.
+  --charset3=<chars>       Custom charset ?3.
+  --charset4=<chars>       Custom charset ?4.
+  --increment              Also try the shorter prefixes of the mask, shortest first.
+  --increment-min=<n>      Shortest length tried with --increment. [default: 1]
+  --increment-max=<n>      Longest length tried with --increment. Default: length of the mask.
+  --skip=<n>               Skip the first n candidates of crack, to resume or split the keyspace. [default: 0]
+  --limit=<n>              Try at most n candidates with crack.
+  --ranges=<file>          Text file with sector ranges. Format: 'start;end'. Every sector range on new line.
+  --mapfile=<file>         GNU ddrescue mapfile of the image, only the finished ('+') blocks are scanned.
+  --wordlist=<file>        Text file with one password per line. Every sector is read once and tried with all of them.
+  --mutate                 Also try common mutations of the passwords: case toggles, leetspeak, appended digits
+                           and years, swapped neighbouring characters.
+  --rules=<file>           Hashcat style rule file, every rule is applied to every password.
+  --backup                 Check the backup header positions at the end of the volume (TrueCrypt 6.0+).
+  --volume-end=<sector>    Last sector of a candidate volume for --backup. Default: end of partition.
+  --format=<format>        Volume format to search for: truecrypt, veracrypt or both. [default: truecrypt]
+  --prf=<prf>              Hash algorithm for the header key derivation: ripemd160, sha512, whirlpool,
+                           sha256, streebog or all. [default: ripemd160]
+  --pim=<pim>              VeraCrypt PIM (Personal Iterations Multiplier). Default: VeraCrypt default iterations.
+  --system                 Volume is an encrypted system partition/drive (pre-boot authentication).
+  --encryption=<names>     Comma separated encryption algorithms to try, e.g. 'AES,Serpent-Twofish-AES', or all.
+                           Fewer algorithms are faster, single ciphers need only part of the derived key.
+                           [default: all]
+  --keyfile=<path>         Keyfile or directory of keyfiles (hidden files and subdirectories are skipped).
+  --checkpoint=<file>      Save the progress of the scan to the file every minute.
+  --reader=<reader>        How the sectors are read: buffered, mmap (memory-mapped, only for images and healthy
+                           drives, a read error ends the program) or direct (O_DIRECT, bypasses the page cache,
+                           Linux only). [default: buffered]
+  --threads=<n>            Threads checking the sectors of the scan. Default: number of CPUs.
+  --resume=<file>          Continue the scan saved in the checkpoint file. Needs the same arguments as the
+                           interrupted scan, the progress is saved to the file again.
 ";
 
 #[derive(Debug, Deserialize)]
 struct Args {
+    cmd_crack: bool,
     arg_path: String,
-    arg_password: String,
-    arg_start: u64,
-    arg_end: u64,
+    arg_password: Option<String>,
+    arg_start: Option<u64>,
+    arg_end: Option<u64>,
     flag_ranges: String,
+    flag_mapfile: Option<String>,
+    flag_wordlist: Option<String>,
+    flag_mutate: bool,
+    flag_rules: Option<String>,
+    flag_backup: bool,
+    flag_volume_end: Vec<u64>,
+    flag_format: FormatArg,
+    flag_prf: PrfArg,
+    flag_pim: Option<u32>,
+    flag_system: bool,
+    flag_encryption: String,
+    flag_keyfile: Vec<String>,
+    flag_offset: u64,
+    flag_mask: Option<String>,
+    flag_charset1: Option<String>,
+    flag_charset2: Option<String>,
+    flag_charset3: Option<String>,
+    flag_charset4: Option<String>,
+    flag_increment: bool,
+    flag_increment_min: usize,
+    flag_increment_max: Option<usize>,
+    flag_skip: u64,
+    flag_limit: Option<u64>,
+    flag_checkpoint: Option<String>,
+    flag_resume: Option<String>,
+    flag_reader: ReaderArg,
+    flag_threads: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+enum FormatArg {
+    TrueCrypt,
+    VeraCrypt,
+    Both,
+}
+
+#[derive(Debug, Deserialize)]
+enum ReaderArg {
+    Buffered,
+    Mmap,
+    Direct,
+}
+
+#[derive(Debug, Deserialize)]
+enum PrfArg {
+    Ripemd160,
+    Sha512,
+    Whirlpool,
+    Sha256,
+    Streebog,
+    All,
 }
 
 fn main() {
     let args: Args = Docopt::new(USAGE).and_then(|d| d.deserialize()).unwrap_or_else(|e| e.exit());
+    if let Err(e) = run(&args) {
+        println!("\x1b\x5b1;31;1m{}\x1b\x5b1;0m", e);
+        process::exit(1);
+    }
+}
+
+fn run(args: &Args) -> Result<(), Box<dyn Error>> {
+    let formats = match args.flag_format {
+        FormatArg::TrueCrypt => vec![VolumeFormat::TrueCrypt],
+        FormatArg::VeraCrypt => vec![VolumeFormat::VeraCrypt],
+        FormatArg::Both => vec![VolumeFormat::TrueCrypt, VolumeFormat::VeraCrypt],
+    };
+    let prfs = match args.flag_prf {
+        PrfArg::Ripemd160 => vec![Prf::Ripemd160],
+        PrfArg::Sha512 => vec![Prf::Sha512],
+        PrfArg::Whirlpool => vec![Prf::Whirlpool],
+        PrfArg::Sha256 => vec![Prf::Sha256],
+        PrfArg::Streebog => vec![Prf::Streebog],
+        PrfArg::All => kdf::ALL_PRFS.to_vec(),
+    };
+    // VeraCrypt supports every PRF, no key derivation means the iteration count of the PIM overflows.
+    let veracrypt = |&prf: &Prf| KeyDerivation::new(VolumeFormat::VeraCrypt, prf, args.flag_pim, args.flag_system);
+    if formats.contains(&VolumeFormat::VeraCrypt) && prfs.iter().any(|prf| veracrypt(prf).is_none()) {
+        return Err("The PIM is too large.".into());
+    }
+    let mut derivations: Vec<KeyDerivation> = Vec::new();
+    for &format in &formats {
+        derivations.extend(prfs.iter().filter_map(|&prf| KeyDerivation::new(format, prf, args.flag_pim, args.flag_system)));
+    }
+    if derivations.is_empty() {
+        return Err("The selected format does not support the selected hash algorithm.".into());
+    }
 
-    let mut tc = TCFinder::new(&args.arg_path);
+    let algorithms = cipher::parse_algorithms(&args.flag_encryption)?;
+    if !derivations.iter().any(|derivation| cipher::algorithms(derivation.format).iter().any(|a| algorithms.contains(a))) {
+        return Err("The selected format does not support the selected encryption algorithms.".into());
+    }
 
-    let sector_ranges = if !args.flag_ranges.is_empty() {
-        read_sector_ranges(&args.flag_ranges)
-    } else {
-        vec![(args.arg_start, args.arg_end)]
+    // The keyfiles are applied to every candidate, the matching password is reported without them.
+    let keyfile_pool = KeyfilePool::new(&keyfile::collect_keyfiles(&args.flag_keyfile)?)?;
+
+    // [options] lets docopt accept the mask options without crack, they must not turn a scan into a crack.
+    let charsets = [&args.flag_charset1, &args.flag_charset2, &args.flag_charset3, &args.flag_charset4];
+    let mask_options = args.flag_mask.is_some() || charsets.iter().any(|charset| charset.is_some())
+        || args.flag_increment || args.flag_increment_min != 1 || args.flag_increment_max.is_some();
+    if mask_options && !args.cmd_crack {
+        return Err("--mask, --charset1 to --charset4 and --increment are only used with crack.".into());
+    }
+    if let Some(ref mask) = args.flag_mask {
+        let charsets = [args.flag_charset1.clone(), args.flag_charset2.clone(),
+                        args.flag_charset3.clone(), args.flag_charset4.clone()];
+        let increment = if args.flag_increment {
+            Some((args.flag_increment_min, args.flag_increment_max))
+        } else {
+            None
+        };
+        let mask = Mask::new(mask, &charsets, increment)?;
+        // crack::candidate_range keeps the indices inside the keyspace.
+        let candidate = |i| mask.candidate(i).expect("Index outside of the mask keyspace.");
+        return crack(args, &derivations, &algorithms, mask.keyspace(), |i| keyfile_pool.apply(&candidate(i)),
+                     |i| String::from_utf8_lossy(&candidate(i)).into_owned());
+    }
+
+    let mut passwords: Vec<Vec<u8>> = Vec::new();
+    if let Some(ref password) = args.arg_password {
+        passwords.push(password.as_bytes().to_vec());
+    }
+    if let Some(ref path) = args.flag_wordlist {
+        passwords.extend(wordlist::read_wordlist(path)?);
+    }
+    if passwords.is_empty() {
+        return Err("No passwords to try.".into());
+    }
+    let rules = match args.flag_rules {
+        Some(ref path) => mutation::read_rules(path)?,
+        None => Vec::new(),
     };
+    // All candidates are tried within the same read of each sector.
+    let candidates = mutation::expand(&passwords, args.flag_mutate, &rules);
+    if candidates.len() > passwords.len() {
+        println!("Trying {} password candidates.", candidates.len());
+    }
+
+    if args.cmd_crack {
+        return crack(args, &derivations, &algorithms, candidates.len() as u64, |i| keyfile_pool.apply(&candidates[i as usize].password), |i| {
+            let candidate = &candidates[i as usize];
+            format!("{} (mutation: {})", String::from_utf8_lossy(&candidate.password), candidate.mutation)
+        });
+    }
+    let keyed_passwords: Vec<Vec<u8>> = candidates.iter().map(|candidate| keyfile_pool.apply(&candidate.password)).collect();
 
-    let results = tc.scan(&sector_ranges, args.arg_password);
+    let reader_kind = match args.flag_reader {
+        ReaderArg::Buffered => ReaderKind::Buffered,
+        ReaderArg::Mmap => ReaderKind::Mmap,
+        ReaderArg::Direct => ReaderKind::Direct,
+    };
+    let mut tc = TCFinder::open(&args.arg_path, reader_kind)?;
 
-    if !results.is_empty() {
-        println!("\x1b\x5b1;32;1mPotential headers: {:?}\x1b\x5b1;0m", results);
+    let sector_ranges = if let Some(ref path) = args.flag_mapfile {
+        ddrescue::mapfile_sector_ranges(path, &tc.read_partition_info()?)?
+    } else if !args.flag_ranges.is_empty() {
+        ranges::read_sector_ranges(&args.flag_ranges)?
+    } else if let (Some(start), Some(end)) = (args.arg_start, args.arg_end) {
+        vec![(start, end)]
     } else {
+        Vec::new()
+    };
+    let config = ScanConfig {
+        algorithms,
+        backup: args.flag_backup,
+        volume_ends: args.flag_volume_end.clone(),
+        checkpoint: args.flag_resume.clone().or_else(|| args.flag_checkpoint.clone()),
+        resume: args.flag_resume.is_some(),
+        threads: args.flag_threads.unwrap_or_else(available_threads),
+        ..ScanConfig::new(sector_ranges, keyed_passwords, derivations)
+    };
+
+    let result = tc.run(&config)?;
+    if !result.unreadable_sectors.is_empty() {
+        println!("\x1b\x5b1;31;1m{} unreadable sectors skipped: {:?}\x1b\x5b1;0m",
+                 result.unreadable_sectors.len(), result.unreadable_sectors);
+    }
+    if result.headers.is_empty() && result.backup_headers.is_empty() {
         println!("\x1b\x5b1;31;1mNo headers found.\x1b\x5b1;0m");
+        return Ok(());
     }
+
+    print_headers("Headers", &result.headers, &candidates);
+    print_headers("Backup headers", &result.backup_headers, &candidates);
+    for (primary, backup) in &result.pairs {
+        println!("\x1b\x5b1;32;1mPrimary header at {} matches backup header at {} (volume size: {})\x1b\x5b1;0m",
+                 primary.sector, backup.sector, primary.header.volume_size);
+    }
+    Ok(())
 }
 
-fn read_sector_ranges(path: &str) -> Vec<(u64, u64)> {
-    use std::io::{BufRead, BufReader};
-    use std::str::FromStr;
+// Tries the candidates of the keyspace selected with --skip and --limit on the header at --offset.
+// candidate(i) returns the password bytes with keyfiles applied, describe(i) the password as reported.
+fn crack<F, D>(args: &Args, derivations: &[KeyDerivation], algorithms: &[EncryptionAlgorithm], keyspace: u64,
+               candidate: F, describe: D) -> Result<(), Box<dyn Error>>
+    where F: Fn(u64) -> Vec<u8> + Sync, D: Fn(u64) -> String
+{
+    let indices = match crack::candidate_range(keyspace, args.flag_skip, args.flag_limit) {
+        Some(indices) => indices,
+        None => return Err(format!("Nothing to try, the keyspace has {} candidates.", keyspace).into()),
+    };
+    println!("Trying candidates {} to {} of {}.", indices.start, indices.end, keyspace);
 
-    let mut sector_ranges: Vec<(u64, u64)> = Vec::new();
-    if let Ok(file) = std::fs::File::open(path) {
-        for line in BufReader::new(file).lines() {
-            let mut line = line.expect("Could not read line.");
-            if line.contains('#') {
-                line = String::from(line.split('#').collect::<Vec<&str>>()[0].trim());
+    let end = indices.end;
+    match crack::crack_file(&args.arg_path, args.flag_offset, derivations, algorithms, indices, candidate)? {
+        Some((index, found)) => {
+            println!("\x1b\x5b1;32;1mPassword found: {} (candidate {})\x1b\x5b1;0m", describe(index), index);
+            println!("{:?}, {:?}, {} iterations: {}",
+                     found.algorithm, found.derivation.prf, found.derivation.iterations, found.header);
+        }
+        None => {
+            println!("\x1b\x5b1;31;1mPassword not found.\x1b\x5b1;0m");
+            if end < keyspace {
+                println!("Continue with --skip={}", end);
             }
+        }
+    }
+    Ok(())
+}
 
-            if !line.is_empty() {
-                let range_str = line.split(';').collect::<Vec<&str>>();
-                sector_ranges.push((
-                    u64::from_str(range_str[0]).expect("Invalid char in range list."),
-                    u64::from_str(range_str[1]).expect("Invalid char in range list."),
-                ));
-            }
+fn print_headers(title: &str, headers: &[FoundHeader], candidates: &[Candidate]) {
+    if headers.is_empty() {
+        return;
+    }
+    let sectors: Vec<u64> = headers.iter().map(|found| found.sector).collect();
+    println!("\x1b\x5b1;32;1m{}: {:?}\x1b\x5b1;0m", title, sectors);
+    for found in headers {
+        let derivation = &found.derivation;
+        if candidates.len() > 1 {
+            let candidate = &candidates[found.password_index];
+            println!("Sector {} password: {} (mutation: {})",
+                     found.sector, String::from_utf8_lossy(&candidate.password), candidate.mutation);
+        }
+        if found.header.is_hidden() {
+            println!("Sector {} (hidden volume, size: {}, {:?}, {:?}, {} iterations): {}", found.sector,
+                     found.header.hidden_volume_size, found.algorithm, derivation.prf, derivation.iterations, found.header);
+        } else {
+            println!("Sector {} ({:?}, {:?}, {} iterations): {}",
+                     found.sector, found.algorithm, derivation.prf, derivation.iterations, found.header);
         }
-    } else {
-        println!("Could not open file for sector ranges.");
     }
-    sector_ranges
 }
@@ -0,0 +1,195 @@
+// Brute-force passwords from a mask with the hashcat syntax: ?l?u?d?d tries every lowercase letter, uppercase letter
+// and two digits. Every candidate has an index in the keyspace, so a search can be resumed or split into ranges.
+
+use error::TcFinderError;
+
+// Built-in charsets.
+const LOWER: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+const UPPER: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const DIGITS: &[u8] = b"0123456789";
+const HEX_LOWER: &[u8] = b"0123456789abcdef";
+const HEX_UPPER: &[u8] = b"0123456789ABCDEF";
+const SPECIAL: &[u8] = b" !\"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~";
+
+// One or more masks of increasing length, enumerated one after the other.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mask {
+    // Characters tried at every position of every mask.
+    masks: Vec<Vec<Vec<u8>>>,
+}
+
+impl Mask {
+    // Parses the mask. ?1 to ?4 refer to the custom charsets, which may themselves contain built-in charsets.
+    // With increment (min, max) the masks of lengths min..=max are tried, each a prefix of the full mask.
+    // max defaults to the length of the mask.
+    pub fn new(mask: &str, custom_charsets: &[Option<String>], increment: Option<(usize, Option<usize>)>)
+               -> Result<Mask, TcFinderError> {
+        Mask::parse(mask, custom_charsets, increment).map_err(TcFinderError::Mask)
+    }
+
+    fn parse(mask: &str, custom_charsets: &[Option<String>], increment: Option<(usize, Option<usize>)>)
+             -> Result<Mask, &'static str> {
+        let mut custom = Vec::new();
+        for charset in custom_charsets {
+            custom.push(match *charset {
+                Some(ref charset) => Some(parse_charset(charset.as_bytes())?),
+                None => None,
+            });
+        }
+        let positions = parse_mask(mask.as_bytes(), &custom)?;
+        if positions.is_empty() {
+            return Err("Mask is empty.");
+        }
+
+        let (min, max) = match increment {
+            Some((min, max)) => (min, max.unwrap_or(positions.len())),
+            None => (positions.len(), positions.len()),
+        };
+        if min == 0 || min > max || max > positions.len() {
+            return Err("Invalid increment length for the mask.");
+        }
+        let mask = Mask { masks: (min..=max).map(|length| positions[..length].to_vec()).collect() };
+        if mask.checked_keyspace().is_none() {
+            return Err("Mask keyspace too large.");
+        }
+        Ok(mask)
+    }
+
+    // Number of candidates in all masks.
+    pub fn keyspace(&self) -> u64 {
+        self.checked_keyspace().unwrap()
+    }
+
+    fn checked_keyspace(&self) -> Option<u64> {
+        let mut total: u64 = 0;
+        for positions in &self.masks {
+            let size = positions.iter().try_fold(1u64, |size, charset| size.checked_mul(charset.len() as u64))?;
+            total = total.checked_add(size)?;
+        }
+        Some(total)
+    }
+
+    // Candidate at the index of the keyspace. The last position changes fastest.
+    pub fn candidate(&self, index: u64) -> Result<Vec<u8>, TcFinderError> {
+        let mut index = index;
+        for positions in &self.masks {
+            let size: u64 = positions.iter().map(|charset| charset.len() as u64).product();
+            if index >= size {
+                index -= size;
+                continue;
+            }
+            let mut password = vec![0u8; positions.len()];
+            for (byte, charset) in password.iter_mut().zip(positions).rev() {
+                let length = charset.len() as u64;
+                *byte = charset[(index % length) as usize];
+                index /= length;
+            }
+            return Ok(password);
+        }
+        Err(TcFinderError::Mask("Index outside of the mask keyspace."))
+    }
+}
+
+// Charsets for every position of the mask. ?? is a literal '?', other characters stand for themselves.
+fn parse_mask(mask: &[u8], custom: &[Option<Vec<u8>>]) -> Result<Vec<Vec<u8>>, &'static str> {
+    let mut positions = Vec::new();
+    let mut i = 0;
+    while i < mask.len() {
+        if mask[i] == b'?' {
+            let name = *mask.get(i + 1).ok_or("Mask ends with '?'.")?;
+            positions.push(charset(name, custom)?);
+            i += 2;
+        } else {
+            positions.push(vec![mask[i]]);
+            i += 1;
+        }
+    }
+    Ok(positions)
+}
+
+// A custom charset: its characters and built-in charsets, duplicates removed.
+fn parse_charset(definition: &[u8]) -> Result<Vec<u8>, &'static str> {
+    let mut result: Vec<u8> = Vec::new();
+    for chars in parse_mask(definition, &[])? {
+        for c in chars {
+            if !result.contains(&c) {
+                result.push(c);
+            }
+        }
+    }
+    if result.is_empty() {
+        return Err("Custom charset is empty.");
+    }
+    Ok(result)
+}
+
+fn charset(name: u8, custom: &[Option<Vec<u8>>]) -> Result<Vec<u8>, &'static str> {
+    Ok(match name {
+        b'l' => LOWER.to_vec(),
+        b'u' => UPPER.to_vec(),
+        b'd' => DIGITS.to_vec(),
+        b'h' => HEX_LOWER.to_vec(),
+        b'H' => HEX_UPPER.to_vec(),
+        b's' => SPECIAL.to_vec(),
+        b'a' => [LOWER, UPPER, DIGITS, SPECIAL].concat(),
+        b'b' => (0..=255).collect(),
+        b'?' => vec![b'?'],
+        b'1'..=b'4' => match custom.get((name - b'1') as usize) {
+            Some(Some(charset)) => charset.clone(),
+            _ => return Err("Custom charset used in mask is not defined."),
+        },
+        _ => return Err("Unknown charset in mask."),
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::Mask;
+
+    #[test]
+    fn test_mask() {
+        let mask = Mask::new("a?d?l", &[], None).unwrap();
+        assert_eq!(mask.keyspace(), 260);
+        assert_eq!(mask.candidate(0).unwrap(), b"a0a".to_vec());
+        assert_eq!(mask.candidate(1).unwrap(), b"a0b".to_vec());
+        assert_eq!(mask.candidate(26).unwrap(), b"a1a".to_vec());
+        assert_eq!(mask.candidate(259).unwrap(), b"a9z".to_vec());
+        assert!(mask.candidate(260).is_err());
+
+        assert_eq!(Mask::new("?a", &[], None).unwrap().keyspace(), 95);
+        assert_eq!(Mask::new("?b?b", &[], None).unwrap().keyspace(), 65536);
+        assert_eq!(Mask::new("??x", &[], None).unwrap().candidate(0).unwrap(), b"?x".to_vec());
+
+        assert!(Mask::new("", &[], None).is_err());
+        assert!(Mask::new("ab?", &[], None).is_err());
+        assert!(Mask::new("?x", &[], None).is_err());
+        assert!(Mask::new("?1", &[], None).is_err());
+        assert!(Mask::new("?b?b?b?b?b?b?b?b?b", &[], None).is_err());
+    }
+
+    #[test]
+    fn test_custom_charsets() {
+        let custom = [Some("?dab".to_string()), None, Some("xx".to_string())];
+        let mask = Mask::new("?1?3", &custom, None).unwrap();
+        assert_eq!(mask.keyspace(), 12);
+        assert_eq!(mask.candidate(11).unwrap(), b"bx".to_vec());
+        assert!(Mask::new("?2", &custom, None).is_err());
+    }
+
+    #[test]
+    fn test_increment() {
+        let mask = Mask::new("?d?d?d", &[], Some((1, Some(3)))).unwrap();
+        assert_eq!(mask.keyspace(), 1110);
+        assert_eq!(mask.candidate(9).unwrap(), b"9".to_vec());
+        assert_eq!(mask.candidate(10).unwrap(), b"00".to_vec());
+        assert_eq!(mask.candidate(109).unwrap(), b"99".to_vec());
+        assert_eq!(mask.candidate(110).unwrap(), b"000".to_vec());
+        assert_eq!(mask.candidate(1109).unwrap(), b"999".to_vec());
+
+        assert_eq!(Mask::new("?d?d?d", &[], Some((2, Some(2)))).unwrap().keyspace(), 100);
+        assert!(Mask::new("?d?d", &[], Some((1, Some(3)))).is_err());
+        assert!(Mask::new("?d?d", &[], Some((0, None))).is_err());
+        assert_eq!(Mask::new("?d?d", &[], Some((1, None))).unwrap().keyspace(), 110);
+    }
+}
@@ -0,0 +1,452 @@
+// Multi-buffer RIPEMD-160 and SHA-512 for PBKDF2: the HMAC iterations of LANES salts run in lockstep,
+// one salt per lane. The lane loops are vectorized by the compiler, with AVX2 if the CPU supports it
+// (detected at runtime), otherwise with the baseline instruction set (SSE2 on x86_64).
+// Only the iterations after the first are computed here, every message is a single padded block.
+
+use std::ops::{Add, BitAnd, BitOr, BitXor, Not};
+
+use crypto::sha2::K64;
+
+// Salts hashed at once.
+pub const LANES: usize = 8;
+
+// Instruction set the lane loops run with. Avx2 must only be used if the CPU supports it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Lanes {
+    Scalar,
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    Avx2,
+}
+
+impl Lanes {
+    // The fastest the CPU supports.
+    pub fn detect() -> Lanes {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        {
+            if is_x86_feature_detected!("avx2") {
+                return Lanes::Avx2;
+            }
+        }
+        Lanes::Scalar
+    }
+}
+
+macro_rules! lanes {
+    ($name:ident, $word:ty) => {
+        // One word of every lane.
+        #[derive(Clone, Copy)]
+        struct $name([$word; LANES]);
+
+        impl $name {
+            #[inline(always)]
+            fn splat(word: $word) -> $name {
+                $name([word; LANES])
+            }
+
+        }
+
+        impl Add for $name {
+            type Output = $name;
+            #[inline(always)]
+            fn add(self, other: $name) -> $name {
+                let mut result = self;
+                for (word, other) in result.0.iter_mut().zip(other.0.iter()) {
+                    *word = word.wrapping_add(*other);
+                }
+                result
+            }
+        }
+
+        impl BitAnd for $name {
+            type Output = $name;
+            #[inline(always)]
+            fn bitand(self, other: $name) -> $name {
+                let mut result = self;
+                for (word, other) in result.0.iter_mut().zip(other.0.iter()) {
+                    *word &= *other;
+                }
+                result
+            }
+        }
+
+        impl BitOr for $name {
+            type Output = $name;
+            #[inline(always)]
+            fn bitor(self, other: $name) -> $name {
+                let mut result = self;
+                for (word, other) in result.0.iter_mut().zip(other.0.iter()) {
+                    *word |= *other;
+                }
+                result
+            }
+        }
+
+        impl BitXor for $name {
+            type Output = $name;
+            #[inline(always)]
+            fn bitxor(self, other: $name) -> $name {
+                let mut result = self;
+                for (word, other) in result.0.iter_mut().zip(other.0.iter()) {
+                    *word ^= *other;
+                }
+                result
+            }
+        }
+
+        impl Not for $name {
+            type Output = $name;
+            #[inline(always)]
+            fn not(self) -> $name {
+                let mut result = self;
+                for word in result.0.iter_mut() {
+                    *word = !*word;
+                }
+                result
+            }
+        }
+    }
+}
+
+lanes!(U32x, u32);
+lanes!(U64x, u64);
+
+impl U32x {
+    #[inline(always)]
+    fn rotate_left(self, n: u32) -> U32x {
+        let mut result = self;
+        for word in result.0.iter_mut() {
+            *word = word.rotate_left(n);
+        }
+        result
+    }
+}
+
+impl U64x {
+    #[inline(always)]
+    fn rotate_right(self, n: u32) -> U64x {
+        let mut result = self;
+        for word in result.0.iter_mut() {
+            *word = word.rotate_right(n);
+        }
+        result
+    }
+
+    #[inline(always)]
+    fn shift_right(self, n: u32) -> U64x {
+        let mut result = self;
+        for word in result.0.iter_mut() {
+            *word >>= n;
+        }
+        result
+    }
+}
+
+// HMAC-RIPEMD-160 key of a password: hash states after the inner and outer key block.
+#[derive(Clone)]
+pub struct Ripemd160Hmac {
+    inner: [u32; 5],
+    outer: [u32; 5],
+}
+
+const RIPEMD160_INIT: [u32; 5] = [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476, 0xc3d2_e1f0];
+const RIPEMD160_BLOCK_SIZE: usize = 64;
+const RIPEMD160_OUTPUT_SIZE: usize = 20;
+
+// Message word and rotation of every step, left and right line.
+const R_LEFT: [usize; 80] = [
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+    7, 4, 13, 1, 10, 6, 15, 3, 12, 0, 9, 5, 2, 14, 11, 8,
+    3, 10, 14, 4, 9, 15, 8, 1, 2, 7, 0, 6, 13, 11, 5, 12,
+    1, 9, 11, 10, 0, 8, 12, 4, 13, 3, 7, 15, 14, 5, 6, 2,
+    4, 0, 5, 9, 7, 12, 2, 10, 14, 1, 3, 8, 11, 6, 15, 13];
+const R_RIGHT: [usize; 80] = [
+    5, 14, 7, 0, 9, 2, 11, 4, 13, 6, 15, 8, 1, 10, 3, 12,
+    6, 11, 3, 7, 0, 13, 5, 10, 14, 15, 8, 12, 4, 9, 1, 2,
+    15, 5, 1, 3, 7, 14, 6, 9, 11, 8, 12, 2, 10, 0, 4, 13,
+    8, 6, 4, 1, 3, 11, 15, 0, 5, 12, 2, 13, 9, 7, 10, 14,
+    12, 15, 10, 4, 1, 5, 8, 7, 6, 2, 13, 14, 0, 3, 9, 11];
+const S_LEFT: [u32; 80] = [
+    11, 14, 15, 12, 5, 8, 7, 9, 11, 13, 14, 15, 6, 7, 9, 8,
+    7, 6, 8, 13, 11, 9, 7, 15, 7, 12, 15, 9, 11, 7, 13, 12,
+    11, 13, 6, 7, 14, 9, 13, 15, 14, 8, 13, 6, 5, 12, 7, 5,
+    11, 12, 14, 15, 14, 15, 9, 8, 9, 14, 5, 6, 8, 6, 5, 12,
+    9, 15, 5, 11, 6, 8, 13, 12, 5, 12, 13, 14, 11, 8, 5, 6];
+const S_RIGHT: [u32; 80] = [
+    8, 9, 9, 11, 13, 15, 15, 5, 7, 7, 8, 11, 14, 14, 12, 6,
+    9, 13, 15, 7, 12, 8, 9, 11, 7, 7, 12, 7, 6, 15, 13, 11,
+    9, 7, 15, 11, 8, 6, 6, 14, 12, 13, 5, 14, 13, 13, 7, 5,
+    15, 5, 8, 11, 14, 14, 6, 14, 6, 9, 12, 9, 12, 5, 15, 8,
+    8, 5, 12, 9, 12, 5, 14, 6, 8, 13, 6, 5, 15, 13, 11, 11];
+const K_LEFT: [u32; 5] = [0x0000_0000, 0x5a82_7999, 0x6ed9_eba1, 0x8f1b_bcdc, 0xa953_fd4e];
+const K_RIGHT: [u32; 5] = [0x50a2_8be6, 0x5c4d_d124, 0x6d70_3ef3, 0x7a6d_76e9, 0x0000_0000];
+
+// Boolean function of the round.
+#[inline(always)]
+fn ripemd160_f(round: usize, x: U32x, y: U32x, z: U32x) -> U32x {
+    match round {
+        0 => x ^ y ^ z,
+        1 => (x & y) | (!x & z),
+        2 => (x | !y) ^ z,
+        3 => (x & z) | (y & !z),
+        _ => x ^ (y | !z),
+    }
+}
+
+#[inline(always)]
+fn ripemd160_compress(state: &mut [U32x; 5], block: &[U32x; 16]) {
+    let (mut al, mut bl, mut cl, mut dl, mut el) = (state[0], state[1], state[2], state[3], state[4]);
+    let (mut ar, mut br, mut cr, mut dr, mut er) = (state[0], state[1], state[2], state[3], state[4]);
+    for j in 0..80 {
+        let round = j / 16;
+        let t = (al + ripemd160_f(round, bl, cl, dl) + block[R_LEFT[j]] + U32x::splat(K_LEFT[round]))
+            .rotate_left(S_LEFT[j]) + el;
+        al = el;
+        el = dl;
+        dl = cl.rotate_left(10);
+        cl = bl;
+        bl = t;
+
+        let t = (ar + ripemd160_f(4 - round, br, cr, dr) + block[R_RIGHT[j]] + U32x::splat(K_RIGHT[round]))
+            .rotate_left(S_RIGHT[j]) + er;
+        ar = er;
+        er = dr;
+        dr = cr.rotate_left(10);
+        cr = br;
+        br = t;
+    }
+    let t = state[1] + cl + dr;
+    state[1] = state[2] + dl + er;
+    state[2] = state[3] + el + ar;
+    state[3] = state[4] + al + br;
+    state[4] = state[0] + bl + cr;
+    state[0] = t;
+}
+
+// Hashes a block of 20 byte state words (a previous hash) after the key block state, the block is padded
+// to 64 + 20 bytes of message.
+#[inline(always)]
+fn ripemd160_hash_digest(key_state: &[u32; 5], digest: &[U32x; 5]) -> [U32x; 5] {
+    let mut block = [U32x::splat(0); 16];
+    block[..5].copy_from_slice(digest);
+    block[5] = U32x::splat(0x80);
+    block[14] = U32x::splat(((RIPEMD160_BLOCK_SIZE + RIPEMD160_OUTPUT_SIZE) * 8) as u32);
+    let mut state = [U32x::splat(0); 5];
+    for (lane, &word) in state.iter_mut().zip(key_state) {
+        *lane = U32x::splat(word);
+    }
+    ripemd160_compress(&mut state, &block);
+    state
+}
+
+#[inline(always)]
+fn ripemd160_iterations(hmac: &Ripemd160Hmac, u: &mut [U32x; 5], iterations: u32) -> [U32x; 5] {
+    let mut t = *u;
+    for _ in 1..iterations {
+        let inner = ripemd160_hash_digest(&hmac.inner, u);
+        *u = ripemd160_hash_digest(&hmac.outer, &inner);
+        for (t, u) in t.iter_mut().zip(u.iter()) {
+            *t = *t ^ *u;
+        }
+    }
+    t
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+unsafe fn ripemd160_iterations_avx2(hmac: &Ripemd160Hmac, u: &mut [U32x; 5], iterations: u32) -> [U32x; 5] {
+    ripemd160_iterations(hmac, u, iterations)
+}
+
+fn ripemd160_iterations_dispatch(hmac: &Ripemd160Hmac, u: &mut [U32x; 5], iterations: u32, lanes: Lanes) -> [U32x; 5] {
+    match lanes {
+        Lanes::Scalar => ripemd160_iterations(hmac, u, iterations),
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        Lanes::Avx2 => {
+            debug_assert!(is_x86_feature_detected!("avx2"));
+            unsafe { ripemd160_iterations_avx2(hmac, u, iterations) }
+        }
+    }
+}
+
+impl Ripemd160Hmac {
+    // block is the HMAC key padded to the block size (kdf::key_block).
+    pub fn new(block: &[u8]) -> Ripemd160Hmac {
+        Ripemd160Hmac { inner: ripemd160_key_state(block, 0x36), outer: ripemd160_key_state(block, 0x5c) }
+    }
+
+    // Runs PBKDF2 iterations 2 to iterations on the first iterations of up to LANES salts.
+    // Returns the PBKDF2 output blocks.
+    pub fn iterate(&self, first: &[Vec<u8>], iterations: u32, lanes: Lanes) -> Vec<Vec<u8>> {
+        assert!(first.len() <= LANES);
+        let mut u = [U32x::splat(0); 5];
+        for (lane, block) in first.iter().enumerate() {
+            for (i, word) in u.iter_mut().enumerate() {
+                word.0[lane] = read_u32_le(&block[i * 4..]);
+            }
+        }
+
+        let t = ripemd160_iterations_dispatch(self, &mut u, iterations, lanes);
+
+        (0..first.len()).map(|lane| t.iter().flat_map(|word| word.0[lane].to_le_bytes().to_vec()).collect()).collect()
+    }
+}
+
+fn ripemd160_key_state(block: &[u8], pad: u8) -> [u32; 5] {
+    let mut words = [U32x::splat(0); 16];
+    for (i, word) in words.iter_mut().enumerate() {
+        let bytes: Vec<u8> = block[i * 4..i * 4 + 4].iter().map(|b| b ^ pad).collect();
+        *word = U32x::splat(read_u32_le(&bytes));
+    }
+    let mut state = [U32x::splat(0); 5];
+    for (lane, &word) in state.iter_mut().zip(&RIPEMD160_INIT) {
+        *lane = U32x::splat(word);
+    }
+    ripemd160_compress(&mut state, &words);
+    let mut result = [0u32; 5];
+    for (word, lane) in result.iter_mut().zip(&state) {
+        *word = lane.0[0];
+    }
+    result
+}
+
+// HMAC-SHA-512 key of a password: hash states after the inner and outer key block.
+#[derive(Clone)]
+pub struct Sha512Hmac {
+    inner: [u64; 8],
+    outer: [u64; 8],
+}
+
+const SHA512_INIT: [u64; 8] = [
+    0x6a09_e667_f3bc_c908, 0xbb67_ae85_84ca_a73b, 0x3c6e_f372_fe94_f82b, 0xa54f_f53a_5f1d_36f1,
+    0x510e_527f_ade6_82d1, 0x9b05_688c_2b3e_6c1f, 0x1f83_d9ab_fb41_bd6b, 0x5be0_cd19_137e_2179];
+const SHA512_BLOCK_SIZE: usize = 128;
+const SHA512_OUTPUT_SIZE: usize = 64;
+
+#[inline(always)]
+fn sha512_compress(state: &mut [U64x; 8], block: &[U64x; 16]) {
+    let mut w = [U64x::splat(0); 80];
+    w[..16].copy_from_slice(block);
+    for t in 16..80 {
+        let s0 = w[t - 15].rotate_right(1) ^ w[t - 15].rotate_right(8) ^ w[t - 15].shift_right(7);
+        let s1 = w[t - 2].rotate_right(19) ^ w[t - 2].rotate_right(61) ^ w[t - 2].shift_right(6);
+        w[t] = w[t - 16] + s0 + w[t - 7] + s1;
+    }
+
+    let (mut a, mut b, mut c, mut d) = (state[0], state[1], state[2], state[3]);
+    let (mut e, mut f, mut g, mut h) = (state[4], state[5], state[6], state[7]);
+    for t in 0..80 {
+        let s1 = e.rotate_right(14) ^ e.rotate_right(18) ^ e.rotate_right(41);
+        let ch = (e & f) ^ (!e & g);
+        let t1 = h + s1 + ch + U64x::splat(K64[t]) + w[t];
+        let s0 = a.rotate_right(28) ^ a.rotate_right(34) ^ a.rotate_right(39);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+        let t2 = s0 + maj;
+        h = g;
+        g = f;
+        f = e;
+        e = d + t1;
+        d = c;
+        c = b;
+        b = a;
+        a = t1 + t2;
+    }
+    for (word, new) in state.iter_mut().zip(&[a, b, c, d, e, f, g, h]) {
+        *word = *word + *new;
+    }
+}
+
+// Hashes a previous hash after the key block state, the block is padded to 128 + 64 bytes of message.
+#[inline(always)]
+fn sha512_hash_digest(key_state: &[u64; 8], digest: &[U64x; 8]) -> [U64x; 8] {
+    let mut block = [U64x::splat(0); 16];
+    block[..8].copy_from_slice(digest);
+    block[8] = U64x::splat(0x8000_0000_0000_0000);
+    block[15] = U64x::splat(((SHA512_BLOCK_SIZE + SHA512_OUTPUT_SIZE) * 8) as u64);
+    let mut state = [U64x::splat(0); 8];
+    for (lane, &word) in state.iter_mut().zip(key_state) {
+        *lane = U64x::splat(word);
+    }
+    sha512_compress(&mut state, &block);
+    state
+}
+
+#[inline(always)]
+fn sha512_iterations(hmac: &Sha512Hmac, u: &mut [U64x; 8], iterations: u32) -> [U64x; 8] {
+    let mut t = *u;
+    for _ in 1..iterations {
+        let inner = sha512_hash_digest(&hmac.inner, u);
+        *u = sha512_hash_digest(&hmac.outer, &inner);
+        for (t, u) in t.iter_mut().zip(u.iter()) {
+            *t = *t ^ *u;
+        }
+    }
+    t
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+unsafe fn sha512_iterations_avx2(hmac: &Sha512Hmac, u: &mut [U64x; 8], iterations: u32) -> [U64x; 8] {
+    sha512_iterations(hmac, u, iterations)
+}
+
+fn sha512_iterations_dispatch(hmac: &Sha512Hmac, u: &mut [U64x; 8], iterations: u32, lanes: Lanes) -> [U64x; 8] {
+    match lanes {
+        Lanes::Scalar => sha512_iterations(hmac, u, iterations),
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        Lanes::Avx2 => {
+            debug_assert!(is_x86_feature_detected!("avx2"));
+            unsafe { sha512_iterations_avx2(hmac, u, iterations) }
+        }
+    }
+}
+
+impl Sha512Hmac {
+    // block is the HMAC key padded to the block size (kdf::key_block).
+    pub fn new(block: &[u8]) -> Sha512Hmac {
+        Sha512Hmac { inner: sha512_key_state(block, 0x36), outer: sha512_key_state(block, 0x5c) }
+    }
+
+    // Runs PBKDF2 iterations 2 to iterations on the first iterations of up to LANES salts.
+    // Returns the PBKDF2 output blocks.
+    pub fn iterate(&self, first: &[Vec<u8>], iterations: u32, lanes: Lanes) -> Vec<Vec<u8>> {
+        assert!(first.len() <= LANES);
+        let mut u = [U64x::splat(0); 8];
+        for (lane, block) in first.iter().enumerate() {
+            for (i, word) in u.iter_mut().enumerate() {
+                word.0[lane] = read_u64_be(&block[i * 8..]);
+            }
+        }
+
+        let t = sha512_iterations_dispatch(self, &mut u, iterations, lanes);
+
+        (0..first.len()).map(|lane| t.iter().flat_map(|word| word.0[lane].to_be_bytes().to_vec()).collect()).collect()
+    }
+}
+
+fn sha512_key_state(block: &[u8], pad: u8) -> [u64; 8] {
+    let mut words = [U64x::splat(0); 16];
+    for (i, word) in words.iter_mut().enumerate() {
+        let bytes: Vec<u8> = block[i * 8..i * 8 + 8].iter().map(|b| b ^ pad).collect();
+        *word = U64x::splat(read_u64_be(&bytes));
+    }
+    let mut state = [U64x::splat(0); 8];
+    for (lane, &word) in state.iter_mut().zip(&SHA512_INIT) {
+        *lane = U64x::splat(word);
+    }
+    sha512_compress(&mut state, &words);
+    let mut result = [0u64; 8];
+    for (word, lane) in result.iter_mut().zip(&state) {
+        *word = lane.0[0];
+    }
+    result
+}
+
+fn read_u32_le(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn read_u64_be(bytes: &[u8]) -> u64 {
+    let mut word = [0u8; 8];
+    word.copy_from_slice(&bytes[..8]);
+    u64::from_be_bytes(word)
+}
@@ -0,0 +1,425 @@
+// Password mutations for passwords that are only remembered approximately.
+// Built-in mutations (case toggles, leetspeak, appended digits and years, transpositions) and rule files with
+// a subset of the hashcat rule syntax expand every password into candidates.
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+use error::TcFinderError;
+
+// Password to try and how it was derived from the given password.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    pub password: Vec<u8>,
+    pub mutation: String,
+}
+
+const LEET: [(u8, u8); 12] = [
+    (b'a', b'4'), (b'a', b'@'), (b'e', b'3'), (b'i', b'1'), (b'i', b'!'), (b'o', b'0'),
+    (b's', b'5'), (b's', b'$'), (b't', b'7'), (b'l', b'1'), (b'g', b'9'), (b'b', b'8'),
+];
+const FIRST_YEAR: u32 = 1950;
+const LAST_YEAR: u32 = 2030;
+
+// Hashcat rule functions that are supported. N and M are positions 0-9, A-Z, X and Y characters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operation {
+    Nothing,                 // :
+    Lowercase,               // l
+    Uppercase,               // u
+    Capitalize,              // c
+    InvertCapitalize,        // C
+    ToggleCase,              // t
+    ToggleAt(usize),         // TN
+    Reverse,                 // r
+    Duplicate,               // d
+    DuplicateTimes(usize),   // pN
+    Reflect,                 // f
+    RotateLeft,              // {
+    RotateRight,             // }
+    Append(u8),              // $X
+    Prepend(u8),             // ^X
+    DeleteFirst,             // [
+    DeleteLast,              // ]
+    DeleteAt(usize),         // DN
+    Extract(usize, usize),   // xNM
+    Omit(usize, usize),      // ONM
+    Insert(usize, u8),       // iNX
+    Overwrite(usize, u8),    // oNX
+    Truncate(usize),         // 'N
+    Replace(u8, u8),         // sXY
+    Purge(u8),               // @X
+    DuplicateFirst(usize),   // zN
+    DuplicateLast(usize),    // ZN
+    DuplicateAll,            // q
+    SwapFront,               // k
+    SwapBack,                // K
+    Swap(usize, usize),      // *NM
+}
+
+// One line of a rule file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    pub text: String,
+    pub operations: Vec<Operation>,
+}
+
+// Expands every password into itself followed by its mutations. Duplicates are removed.
+pub fn expand(passwords: &[Vec<u8>], builtin: bool, rules: &[Rule]) -> Vec<Candidate> {
+    let mut seen = HashSet::new();
+    let mut candidates = Vec::new();
+    let mut add = |password: Vec<u8>, mutation: String| {
+        if !password.is_empty() && seen.insert(password.clone()) {
+            candidates.push(Candidate { password, mutation });
+        }
+    };
+
+    for password in passwords {
+        add(password.clone(), String::from("none"));
+        if builtin {
+            for (mutated, mutation) in builtin_mutations(password) {
+                add(mutated, mutation);
+            }
+        }
+        for rule in rules {
+            add(apply_rule(&rule.operations, password), format!("rule '{}'", rule.text));
+        }
+    }
+    candidates
+}
+
+pub fn builtin_mutations(password: &[u8]) -> Vec<(Vec<u8>, String)> {
+    let mut mutations = vec![
+        (password.to_ascii_lowercase(), String::from("lowercase")),
+        (password.to_ascii_uppercase(), String::from("uppercase")),
+        (apply_operation(Operation::Capitalize, password), String::from("capitalize")),
+        (apply_operation(Operation::InvertCapitalize, password), String::from("invert capitalize")),
+    ];
+    for i in 0..password.len() {
+        if password[i].is_ascii_alphabetic() {
+            mutations.push((apply_operation(Operation::ToggleAt(i), password), format!("toggle case at {}", i)));
+        }
+    }
+
+    // Leetspeak in both directions, the password might have been remembered with or without it.
+    let mut all_leet = password.to_vec();
+    for &(letter, leet) in &LEET {
+        let to_leet: Vec<u8> = password.iter().map(|&c| if c.to_ascii_lowercase() == letter { leet } else { c }).collect();
+        mutations.push((to_leet, format!("leet {}->{}", letter as char, leet as char)));
+        let from_leet: Vec<u8> = password.iter().map(|&c| if c == leet { letter } else { c }).collect();
+        mutations.push((from_leet, format!("leet {}->{}", leet as char, letter as char)));
+        for c in all_leet.iter_mut().filter(|c| c.to_ascii_lowercase() == letter) {
+            *c = leet;
+        }
+    }
+    mutations.push((all_leet, String::from("leet all")));
+
+    for digit in 0..10 {
+        mutations.push((append(password, &format!("{}", digit)), format!("append {}", digit)));
+    }
+    for digits in 0..100 {
+        mutations.push((append(password, &format!("{:02}", digits)), format!("append {:02}", digits)));
+    }
+    for year in FIRST_YEAR..=LAST_YEAR {
+        mutations.push((append(password, &format!("{}", year)), format!("append {}", year)));
+    }
+
+    for i in 1..password.len() {
+        mutations.push((apply_operation(Operation::Swap(i - 1, i), password), format!("swap {}-{}", i - 1, i)));
+    }
+    mutations
+}
+
+fn append(password: &[u8], suffix: &str) -> Vec<u8> {
+    let mut result = password.to_vec();
+    result.extend_from_slice(suffix.as_bytes());
+    result
+}
+
+// Rule file: one rule per line, empty lines and lines starting with '#' are skipped.
+pub fn read_rules(path: &str) -> Result<Vec<Rule>, TcFinderError> {
+    let file = File::open(path).map_err(|e| TcFinderError::file(path, e))?;
+    let mut rules = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line.map_err(|e| TcFinderError::file(path, e))?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        rules.push(Rule { operations: parse_rule(&line)?, text: line });
+    }
+    Ok(rules)
+}
+
+pub fn parse_rule(rule: &str) -> Result<Vec<Operation>, TcFinderError> {
+    parse_operations(rule).map_err(TcFinderError::Rule)
+}
+
+fn parse_operations(rule: &str) -> Result<Vec<Operation>, &'static str> {
+    let mut operations = Vec::new();
+    let mut chars = rule.bytes();
+    while let Some(function) = chars.next() {
+        let mut next = || chars.next().ok_or("Rule function is missing an argument.");
+        let operation = match function {
+            b' ' | b'\t' => continue,
+            b':' => Operation::Nothing,
+            b'l' => Operation::Lowercase,
+            b'u' => Operation::Uppercase,
+            b'c' => Operation::Capitalize,
+            b'C' => Operation::InvertCapitalize,
+            b't' => Operation::ToggleCase,
+            b'T' => Operation::ToggleAt(position(next()?)?),
+            b'r' => Operation::Reverse,
+            b'd' => Operation::Duplicate,
+            b'p' => Operation::DuplicateTimes(position(next()?)?),
+            b'f' => Operation::Reflect,
+            b'{' => Operation::RotateLeft,
+            b'}' => Operation::RotateRight,
+            b'$' => Operation::Append(next()?),
+            b'^' => Operation::Prepend(next()?),
+            b'[' => Operation::DeleteFirst,
+            b']' => Operation::DeleteLast,
+            b'D' => Operation::DeleteAt(position(next()?)?),
+            b'x' => Operation::Extract(position(next()?)?, position(next()?)?),
+            b'O' => Operation::Omit(position(next()?)?, position(next()?)?),
+            b'i' => Operation::Insert(position(next()?)?, next()?),
+            b'o' => Operation::Overwrite(position(next()?)?, next()?),
+            b'\'' => Operation::Truncate(position(next()?)?),
+            b's' => Operation::Replace(next()?, next()?),
+            b'@' => Operation::Purge(next()?),
+            b'z' => Operation::DuplicateFirst(position(next()?)?),
+            b'Z' => Operation::DuplicateLast(position(next()?)?),
+            b'q' => Operation::DuplicateAll,
+            b'k' => Operation::SwapFront,
+            b'K' => Operation::SwapBack,
+            b'*' => Operation::Swap(position(next()?)?, position(next()?)?),
+            _ => return Err("Unsupported rule function."),
+        };
+        operations.push(operation);
+    }
+    Ok(operations)
+}
+
+// Positions are 0-9 followed by A-Z for 10-35.
+fn position(c: u8) -> Result<usize, &'static str> {
+    match c {
+        b'0'..=b'9' => Ok(usize::from(c - b'0')),
+        b'A'..=b'Z' => Ok(usize::from(c - b'A') + 10),
+        _ => Err("Invalid position in rule."),
+    }
+}
+
+pub fn apply_rule(operations: &[Operation], password: &[u8]) -> Vec<u8> {
+    operations.iter().fold(password.to_vec(), |word, &operation| apply_operation(operation, &word))
+}
+
+// Operations with a position outside of the word leave it unchanged, as in hashcat.
+fn apply_operation(operation: Operation, word: &[u8]) -> Vec<u8> {
+    let mut w = word.to_vec();
+    let len = w.len();
+    match operation {
+        Operation::Nothing => {}
+        Operation::Lowercase => w.make_ascii_lowercase(),
+        Operation::Uppercase => w.make_ascii_uppercase(),
+        Operation::Capitalize => {
+            w.make_ascii_lowercase();
+            if let Some(first) = w.first_mut() {
+                first.make_ascii_uppercase();
+            }
+        }
+        Operation::InvertCapitalize => {
+            w.make_ascii_uppercase();
+            if let Some(first) = w.first_mut() {
+                first.make_ascii_lowercase();
+            }
+        }
+        Operation::ToggleCase => w.iter_mut().for_each(toggle),
+        Operation::ToggleAt(n) => {
+            if let Some(c) = w.get_mut(n) {
+                toggle(c);
+            }
+        }
+        Operation::Reverse => w.reverse(),
+        Operation::Duplicate => w.extend_from_slice(word),
+        Operation::DuplicateTimes(n) => {
+            for _ in 0..n {
+                w.extend_from_slice(word);
+            }
+        }
+        Operation::Reflect => w.extend(word.iter().rev()),
+        Operation::RotateLeft => {
+            if len > 0 {
+                w.rotate_left(1);
+            }
+        }
+        Operation::RotateRight => {
+            if len > 0 {
+                w.rotate_right(1);
+            }
+        }
+        Operation::Append(c) => w.push(c),
+        Operation::Prepend(c) => w.insert(0, c),
+        Operation::DeleteFirst => {
+            if len > 0 {
+                w.remove(0);
+            }
+        }
+        Operation::DeleteLast => {
+            w.pop();
+        }
+        Operation::DeleteAt(n) => {
+            if n < len {
+                w.remove(n);
+            }
+        }
+        Operation::Extract(n, m) => {
+            if n + m <= len {
+                w = word[n..n + m].to_vec();
+            }
+        }
+        Operation::Omit(n, m) => {
+            if n + m <= len {
+                w.drain(n..n + m);
+            }
+        }
+        Operation::Insert(n, c) => {
+            if n <= len {
+                w.insert(n, c);
+            }
+        }
+        Operation::Overwrite(n, c) => {
+            if n < len {
+                w[n] = c;
+            }
+        }
+        Operation::Truncate(n) => w.truncate(n),
+        Operation::Replace(x, y) => w.iter_mut().filter(|c| **c == x).for_each(|c| *c = y),
+        Operation::Purge(x) => w.retain(|&c| c != x),
+        Operation::DuplicateFirst(n) => {
+            if len > 0 {
+                for _ in 0..n {
+                    w.insert(0, word[0]);
+                }
+            }
+        }
+        Operation::DuplicateLast(n) => {
+            if len > 0 {
+                for _ in 0..n {
+                    w.push(word[len - 1]);
+                }
+            }
+        }
+        Operation::DuplicateAll => w = word.iter().flat_map(|&c| vec![c, c]).collect(),
+        Operation::SwapFront => {
+            if len > 1 {
+                w.swap(0, 1);
+            }
+        }
+        Operation::SwapBack => {
+            if len > 1 {
+                w.swap(len - 2, len - 1);
+            }
+        }
+        Operation::Swap(n, m) => {
+            if n < len && m < len {
+                w.swap(n, m);
+            }
+        }
+    }
+    w
+}
+
+fn toggle(c: &mut u8) {
+    if c.is_ascii_lowercase() {
+        c.make_ascii_uppercase();
+    } else if c.is_ascii_uppercase() {
+        c.make_ascii_lowercase();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_rule, parse_rule, Operation, Rule};
+
+    fn apply(rule: &str, password: &str) -> String {
+        String::from_utf8(apply_rule(&parse_rule(rule).unwrap(), password.as_bytes())).unwrap()
+    }
+
+    #[test]
+    fn test_rules() {
+        assert_eq!(apply(":", "p4ssWord"), "p4ssWord");
+        assert_eq!(apply("l", "p4ssWord"), "p4ssword");
+        assert_eq!(apply("u", "p4ssWord"), "P4SSWORD");
+        assert_eq!(apply("c", "p4ssWord"), "P4ssword");
+        assert_eq!(apply("C", "p4ssWord"), "p4SSWORD");
+        assert_eq!(apply("t", "p4ssWord"), "P4SSwORD");
+        assert_eq!(apply("T4 T9", "p4ssWord"), "p4ssword");
+        assert_eq!(apply("r", "abc"), "cba");
+        assert_eq!(apply("d", "abc"), "abcabc");
+        assert_eq!(apply("p2", "abc"), "abcabcabc");
+        assert_eq!(apply("f", "abc"), "abccba");
+        assert_eq!(apply("{", "abc"), "bca");
+        assert_eq!(apply("}", "abc"), "cab");
+        assert_eq!(apply("$1 $9 $9 $5", "abc"), "abc1995");
+        assert_eq!(apply("^1^ ", "abc"), " 1abc");
+        assert_eq!(apply("[", "abc"), "bc");
+        assert_eq!(apply("]", "abc"), "ab");
+        assert_eq!(apply("D1", "abc"), "ac");
+        assert_eq!(apply("x12", "abcd"), "bc");
+        assert_eq!(apply("O12", "abcd"), "ad");
+        assert_eq!(apply("i1!", "abc"), "a!bc");
+        assert_eq!(apply("o0X", "abc"), "Xbc");
+        assert_eq!(apply("'2", "abc"), "ab");
+        assert_eq!(apply("sa4se3", "aeae"), "4343");
+        assert_eq!(apply("@a", "banana"), "bnn");
+        assert_eq!(apply("z2", "abc"), "aaabc");
+        assert_eq!(apply("Z2", "abc"), "abccc");
+        assert_eq!(apply("q", "abc"), "aabbcc");
+        assert_eq!(apply("k", "abc"), "bac");
+        assert_eq!(apply("K", "abc"), "acb");
+        assert_eq!(apply("*02", "abc"), "cba");
+        // Positions outside of the word leave it unchanged.
+        assert_eq!(apply("D5 *09 oA!", "abc"), "abc");
+
+        assert_eq!(parse_rule("TA").unwrap(), vec![Operation::ToggleAt(10)]);
+        assert!(parse_rule("$").is_err());
+        assert!(parse_rule("T!").is_err());
+        assert!(parse_rule("Y").is_err());
+    }
+
+    #[test]
+    fn test_builtin_mutations() {
+        let mutations = super::builtin_mutations(b"Password");
+        let has = |password: &str, mutation: &str| {
+            mutations.iter().any(|m| m.0 == password.as_bytes() && m.1 == mutation)
+        };
+        assert!(has("password", "lowercase"));
+        assert!(has("PASSWORD", "uppercase"));
+        assert!(has("PAssword", "toggle case at 1"));
+        assert!(has("P4ssword", "leet a->4"));
+        assert!(has("P455w0rd", "leet all"));
+        assert!(has("Password7", "append 7"));
+        assert!(has("Password07", "append 07"));
+        assert!(has("Password2019", "append 2019"));
+        assert!(has("Psasword", "swap 1-2"));
+        assert!(super::builtin_mutations(b"p4ssword").iter().any(|m| m.0 == b"password" && m.1 == "leet 4->a"));
+    }
+
+    #[test]
+    fn test_expand() {
+        let rules = vec![
+            Rule { text: String::from("c"), operations: parse_rule("c").unwrap() },
+            Rule { text: String::from(":"), operations: parse_rule(":").unwrap() },
+            Rule { text: String::from("$!"), operations: parse_rule("$!").unwrap() },
+        ];
+        let candidates = super::expand(&[b"pass".to_vec(), b"Pass".to_vec()], false, &rules);
+        let passwords: Vec<&[u8]> = candidates.iter().map(|c| &c.password[..]).collect();
+        assert_eq!(passwords, vec![&b"pass"[..], b"Pass", b"pass!", b"Pass!"]);
+        assert_eq!(candidates[1].mutation, "rule 'c'");
+        assert_eq!(candidates[3].mutation, "rule '$!'");
+
+        assert_eq!(super::expand(&[b"pass".to_vec()], false, &[]).len(), 1);
+        assert!(super::expand(&[b"pass".to_vec()], true, &[]).len() > 100);
+    }
+}
@@ -2,6 +2,8 @@ use std::fs::File;
 use std::io::Read;
 use std::fmt;
 
+use error::TcFinderError;
+
 // Info in the first 512 Bytes of the partition
 #[derive(Default, Debug, Clone, Copy)]
 pub struct PartitionInfo {
@@ -27,25 +29,34 @@ impl fmt::Display for PartitionInfo {
     }
 }
 
-pub fn read_info_ntfs(partition: &mut File) -> Result<PartitionInfo, &'static str> {
+// Reads the boot sector at the current position of the file, which has to be the start of the partition.
+pub fn read_info_ntfs(partition: &mut File) -> Result<PartitionInfo, TcFinderError> {
         let mut buffer = [0u8; 512];
-        if partition.read(&mut buffer).expect("Reading first sector of partition failed.") != 512 {
-            return Err("Failed to read the full first 512 Bytes of partition. Bytes missing.")
+        let length = partition.read(&mut buffer).map_err(|e| TcFinderError::io(0, e))?;
+        if length != 512 {
+            return Err(TcFinderError::UnsupportedFilesystem("First sector of the partition is incomplete."));
+        }
+        // Fields are little-endian and unaligned.
+        if buffer[0x03..0x0B] != *b"NTFS    " {
+            return Err(TcFinderError::UnsupportedFilesystem("Filesystem is not NTFS."));
         }
-        let buffer_ptr = &buffer as *const _;
-
-        let mut info = PartitionInfo::default();
-        unsafe {
-            let filesystem_identifier: u64 = *((buffer_ptr as u64 + 0x03) as *const u64);
-            if filesystem_identifier != 0x2020_2020_5346_544E {
-                return Err("Filesystem is not NTFS.");
-            }
 
-            info.bytes_per_sector = *((buffer_ptr as u64 + 0x0B) as *const u16);
-            info.sectors_in_cluster = *((buffer_ptr as u64 + 0x0D) as *const u8);
-            info.total_sectors_in_partition = *((buffer_ptr as u64 + 0x28) as *const u64);
-            info.cluster_containing_mft = *((buffer_ptr as u64 + 0x30) as *const u64);
+        // The scan reads whole sectors and the header is 512 bytes, other sizes would not fit its buffers.
+        let bytes_per_sector = u16::from_le_bytes([buffer[0x0B], buffer[0x0C]]);
+        if !bytes_per_sector.is_power_of_two() || !(512..=4096).contains(&bytes_per_sector) {
+            return Err(TcFinderError::UnsupportedFilesystem("Bytes per sector is not 512, 1024, 2048 or 4096."));
         }
 
-        Ok(info)
+        Ok(PartitionInfo {
+            bytes_per_sector,
+            sectors_in_cluster: buffer[0x0D],
+            total_sectors_in_partition: read_u64_le(&buffer[0x28..0x30]),
+            cluster_containing_mft: read_u64_le(&buffer[0x30..0x38]),
+        })
     }
+
+fn read_u64_le(bytes: &[u8]) -> u64 {
+    let mut number = [0u8; 8];
+    number.copy_from_slice(bytes);
+    u64::from_le_bytes(number)
+}
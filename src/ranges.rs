@@ -0,0 +1,63 @@
+// Sector range lists: one 'start;end' range of sectors per line, both inclusive. '#' starts a comment.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+use error::TcFinderError;
+
+pub fn read_sector_ranges(path: &str) -> Result<Vec<(u64, u64)>, TcFinderError> {
+    let file = File::open(path).map_err(|e| TcFinderError::file(path, e))?;
+    let mut lines = Vec::new();
+    for line in BufReader::new(file).lines() {
+        lines.push(line.map_err(|e| TcFinderError::file(path, e))?);
+    }
+    parse_sector_ranges(&lines)
+}
+
+fn parse_sector_ranges<S: AsRef<str>>(lines: &[S]) -> Result<Vec<(u64, u64)>, TcFinderError> {
+    let mut sector_ranges = Vec::new();
+    for (number, line) in lines.iter().enumerate() {
+        let line = line.as_ref().split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let mut fields = line.split(';').map(|field| field.trim().parse::<u64>());
+        let (start, end) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(Ok(start)), Some(Ok(end)), None) => (start, end),
+            _ => return Err(TcFinderError::InvalidRangeLine { line: number + 1 }),
+        };
+        if start > end {
+            return Err(TcFinderError::InvalidRange { start, end });
+        }
+        sector_ranges.push((start, end));
+    }
+    Ok(sector_ranges)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use error::TcFinderError;
+
+    #[test]
+    fn test_parse_sector_ranges() {
+        let lines = ["# ranges", "0;99", "", " 200 ; 299 # second", "5000;5000"];
+        assert_eq!(super::parse_sector_ranges(&lines).unwrap(), [(0, 99), (200, 299), (5000, 5000)]);
+
+        for &(line, number) in &[("100", 2), ("1;2;3", 2), ("x;10", 2), ("10;", 2), ("-1;10", 2)] {
+            match super::parse_sector_ranges(&["0;1", line]) {
+                Err(TcFinderError::InvalidRangeLine { line }) => assert_eq!(line, number),
+                other => panic!("{} {:?}", line, other),
+            }
+        }
+        match super::parse_sector_ranges(&["30;10"]) {
+            Err(TcFinderError::InvalidRange { start: 30, end: 10 }) => (),
+            other => panic!("{:?}", other),
+        }
+        match super::read_sector_ranges("/nonexistent/ranges.txt") {
+            Err(TcFinderError::File { ref path, .. }) => assert_eq!(path, "/nonexistent/ranges.txt"),
+            other => panic!("{:?}", other),
+        }
+    }
+}
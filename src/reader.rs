@@ -0,0 +1,308 @@
+// Reading the scanned sectors. Every buffer of sectors is read into a chunk that the jobs checking its sectors
+// share by reference counting, nothing is copied per job.
+//
+// Readers:
+// - Buffered: normal reads through the page cache, works everywhere.
+// - Mmap: the drive or image is memory-mapped and chunks point into the mapping, the sectors are not copied at all.
+//   A read error of a mapped page kills the process (SIGBUS), only for images and healthy drives.
+// - Direct: O_DIRECT reads into aligned buffers, bypassing the page cache which a scan would only fill with data
+//   read once. Linux only.
+// The mmap and direct readers fall back to buffered reads (with the retries of read_sectors) for sectors they cannot
+// read, e.g. past the end of a truncated image.
+
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::{self, SeekFrom};
+use std::ops::Deref;
+#[cfg(target_os = "linux")]
+use std::os::unix::fs::OpenOptionsExt;
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+use std::sync::Arc;
+#[cfg(target_os = "linux")]
+use std::fs::OpenOptions;
+#[cfg(unix)]
+use std::{ptr, slice};
+
+#[cfg(unix)]
+use libc;
+
+// Alignment of the offset, length and memory of O_DIRECT reads, the largest logical block size of common drives.
+#[cfg(target_os = "linux")]
+const DIRECT_ALIGNMENT: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReaderKind {
+    Buffered,
+    Mmap,
+    Direct,
+}
+
+pub struct SectorReader {
+    // Also used for the partition info, single sectors and the fallback of the other readers.
+    file: File,
+    backend: Backend,
+}
+
+enum Backend {
+    Buffered,
+    #[cfg(unix)]
+    Mapped(Arc<Mmap>),
+    #[cfg(target_os = "linux")]
+    Direct(File),
+}
+
+// Sectors read by SectorReader::read_chunk. Clones share the data.
+#[derive(Clone)]
+pub struct Chunk {
+    data: ChunkData,
+    start: usize,
+    len: usize,
+}
+
+#[derive(Clone)]
+enum ChunkData {
+    Buffer(Arc<Vec<u8>>),
+    #[cfg(unix)]
+    Mapped(Arc<Mmap>),
+}
+
+impl Deref for Chunk {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        let data: &[u8] = match self.data {
+            ChunkData::Buffer(ref buffer) => buffer,
+            #[cfg(unix)]
+            ChunkData::Mapped(ref map) => map.as_slice(),
+        };
+        &data[self.start..self.start + self.len]
+    }
+}
+
+impl SectorReader {
+    pub fn open(path: &str, kind: ReaderKind) -> io::Result<SectorReader> {
+        let mut file = File::open(path)?;
+        let backend = match kind {
+            ReaderKind::Buffered => Backend::Buffered,
+            #[cfg(unix)]
+            ReaderKind::Mmap => Backend::Mapped(Arc::new(Mmap::map(&mut file)?)),
+            #[cfg(target_os = "linux")]
+            ReaderKind::Direct => Backend::Direct(OpenOptions::new().read(true).custom_flags(libc::O_DIRECT).open(path)?),
+            #[allow(unreachable_patterns)]
+            _ => return Err(io::Error::other(format!("The {:?} reader is not supported on this system.", kind))),
+        };
+        Ok(SectorReader { file, backend })
+    }
+
+    pub fn file(&mut self) -> &mut File {
+        &mut self.file
+    }
+
+    // Reads the sectors starting at first_sector. Unreadable sectors are appended to unreadable and zeroed,
+    // see read_sectors.
+    pub fn read_chunk(&mut self, first_sector: u64, sectors: usize, sector_size: u64, unreadable: &mut Vec<u64>) -> Chunk {
+        let offset = first_sector * sector_size;
+        let len = sectors * sector_size as usize;
+        match self.backend {
+            Backend::Buffered => (),
+            #[cfg(unix)]
+            Backend::Mapped(ref map) => {
+                if offset + len as u64 <= map.len as u64 {
+                    return Chunk { data: ChunkData::Mapped(Arc::clone(map)), start: offset as usize, len };
+                }
+            }
+            #[cfg(target_os = "linux")]
+            Backend::Direct(ref mut direct) => {
+                if let Ok(chunk) = read_direct(direct, offset, len) {
+                    return chunk;
+                }
+            }
+        }
+        let mut buffer = vec![0u8; len];
+        self.read_sectors(first_sector, sector_size, &mut buffer, unreadable);
+        Chunk { data: ChunkData::Buffer(Arc::new(buffer)), start: 0, len }
+    }
+
+    pub fn read_sectors(&mut self, first_sector: u64, sector_size: u64, buf: &mut [u8], unreadable: &mut Vec<u64>) -> bool {
+        read_sectors(&mut self.file, first_sector, sector_size, buf, unreadable)
+    }
+}
+
+// Reads the aligned area around the sectors, the chunk starts at the first requested byte.
+#[cfg(target_os = "linux")]
+fn read_direct(direct: &mut File, offset: u64, len: usize) -> io::Result<Chunk> {
+    let alignment = DIRECT_ALIGNMENT as u64;
+    let aligned_offset = offset / alignment * alignment;
+    let aligned_len = (offset + len as u64 - aligned_offset).div_ceil(alignment) as usize * DIRECT_ALIGNMENT;
+    let mut buffer = vec![0u8; aligned_len + DIRECT_ALIGNMENT];
+    let skip = buffer.as_ptr().align_offset(DIRECT_ALIGNMENT);
+
+    direct.seek(SeekFrom::Start(aligned_offset))?;
+    direct.read_exact(&mut buffer[skip..skip + aligned_len])?;
+    Ok(Chunk { data: ChunkData::Buffer(Arc::new(buffer)), start: skip + (offset - aligned_offset) as usize, len })
+}
+
+// Read-only shared mapping of a whole file or block device.
+#[cfg(unix)]
+struct Mmap {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+// The mapping is never written and lives until the last chunk referencing it is dropped.
+#[cfg(unix)]
+unsafe impl Send for Mmap {}
+#[cfg(unix)]
+unsafe impl Sync for Mmap {}
+
+#[cfg(unix)]
+impl Mmap {
+    fn map(file: &mut File) -> io::Result<Mmap> {
+        // The metadata of a block device has no size, seeking to the end gives it.
+        let len = file.seek(SeekFrom::End(0))? as usize;
+        if len == 0 {
+            return Ok(Mmap { ptr: ptr::null_mut(), len: 0 });
+        }
+        let ptr = unsafe { libc::mmap(ptr::null_mut(), len, libc::PROT_READ, libc::MAP_SHARED, file.as_raw_fd(), 0) };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Mmap { ptr, len })
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        if self.len == 0 {
+            return &[];
+        }
+        unsafe { slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        if self.len > 0 {
+            unsafe { libc::munmap(self.ptr, self.len) };
+        }
+    }
+}
+
+// Reads the sectors starting at first_sector into buf. A failed read is retried in halves down to single sectors,
+// failing drives often have only a few bad sectors. Sectors that still cannot be read are appended to unreadable
+// and zeroed, which skips them like empty sectors. Returns false if any sector was unreadable.
+fn read_sectors<R: Read + Seek>(reader: &mut R, first_sector: u64, sector_size: u64, buf: &mut [u8],
+                                unreadable: &mut Vec<u64>) -> bool {
+    let offset = first_sector * sector_size;
+    if reader.seek(SeekFrom::Start(offset)).and_then(|_| reader.read_exact(buf)).is_ok() {
+        return true;
+    }
+    let sectors = buf.len() as u64 / sector_size;
+    if sectors <= 1 {
+        buf.iter_mut().for_each(|byte| *byte = 0);
+        unreadable.push(first_sector);
+        return false;
+    }
+    let (first, second) = buf.split_at_mut((sectors / 2 * sector_size) as usize);
+    let first_read = read_sectors(reader, first_sector, sector_size, first, unreadable);
+    let second_read = read_sectors(reader, first_sector + sectors / 2, sector_size, second, unreadable);
+    first_read && second_read
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Cursor, Read, Seek, SeekFrom};
+    use std::{env, fs};
+
+    #[cfg(target_os = "linux")]
+    use libc;
+
+    use super::{ReaderKind, SectorReader};
+
+    // Fails every read that touches one of the bad sectors (512 bytes each).
+    struct BadSectorReader {
+        data: Cursor<Vec<u8>>,
+        bad_sectors: Vec<u64>,
+    }
+
+    impl Read for BadSectorReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let start = self.data.position() / 512;
+            let end = (self.data.position() + buf.len() as u64).div_ceil(512);
+            if self.bad_sectors.iter().any(|&sector| start <= sector && sector < end) {
+                return Err(io::Error::other("bad sector"));
+            }
+            self.data.read(buf)
+        }
+    }
+
+    impl Seek for BadSectorReader {
+        fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+            self.data.seek(position)
+        }
+    }
+
+    #[test]
+    fn test_read_sectors() {
+        let data: Vec<u8> = (0..200 * 512).map(|i| (i / 512) as u8 | 1).collect();
+        let mut reader = BadSectorReader { data: Cursor::new(data), bad_sectors: vec![13, 14, 60] };
+        let mut unreadable = Vec::new();
+
+        let mut buf = vec![0u8; 32 * 512];
+        assert!(!super::read_sectors(&mut reader, 10, 512, &mut buf, &mut unreadable));
+        assert_eq!(unreadable, [13, 14]);
+        for (i, sector) in buf.chunks(512).enumerate() {
+            let expected = if i == 3 || i == 4 { 0 } else { (10 + i) as u8 | 1 };
+            assert!(sector.iter().all(|&byte| byte == expected));
+        }
+
+        assert!(super::read_sectors(&mut reader, 70, 512, &mut buf, &mut unreadable));
+        assert!(!super::read_sectors(&mut reader, 60, 512, &mut buf[..512], &mut unreadable));
+        assert_eq!(unreadable, [13, 14, 60]);
+    }
+
+    #[test]
+    fn test_read_chunk() {
+        // 100 sectors, the last 4 KiB block is incomplete.
+        let data: Vec<u8> = (0..100 * 512).map(|i| (i / 512) as u8 | 1).collect();
+        let path = env::temp_dir().join("tcfinder_test_read_chunk.img");
+        fs::write(&path, &data).unwrap();
+
+        let mut buffered = Vec::new();
+        for &kind in &[ReaderKind::Buffered, ReaderKind::Mmap, ReaderKind::Direct] {
+            let mut reader = match SectorReader::open(path.to_str().unwrap(), kind) {
+                Ok(reader) => reader,
+                // The filesystem of the temp dir refuses O_DIRECT (e.g. tmpfs), any other error fails the test.
+                #[cfg(target_os = "linux")]
+                Err(ref e) if kind == ReaderKind::Direct && e.raw_os_error() == Some(libc::EINVAL) => continue,
+                // Mmap is unix only, Direct linux only.
+                Err(_) if (kind == ReaderKind::Mmap && !cfg!(unix)) || (kind == ReaderKind::Direct && !cfg!(target_os = "linux")) => continue,
+                Err(e) => panic!("{:?}: {}", kind, e),
+            };
+            let mut unreadable = Vec::new();
+
+            let chunk = reader.read_chunk(3, 20, 512, &mut unreadable);
+            assert_eq!(&chunk[..], &data[3 * 512..23 * 512]);
+            // Every reader returns the bytes of the buffered reader.
+            if kind == ReaderKind::Buffered {
+                buffered = chunk.to_vec();
+            } else {
+                assert_eq!(&chunk[..], &buffered[..], "{:?}", kind);
+            }
+            let shared = chunk.clone();
+            drop(chunk);
+            assert_eq!(&shared[512..1024], &data[4 * 512..5 * 512]);
+
+            assert_eq!(&reader.read_chunk(90, 10, 512, &mut unreadable)[..], &data[90 * 512..]);
+            assert!(unreadable.is_empty());
+
+            // Sectors past the end of the image are zeroed and reported.
+            let chunk = reader.read_chunk(96, 8, 512, &mut unreadable);
+            assert_eq!(&chunk[..4 * 512], &data[96 * 512..]);
+            assert!(chunk[4 * 512..].iter().all(|&byte| byte == 0));
+            assert_eq!(unreadable, [100, 101, 102, 103], "{:?}", kind);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
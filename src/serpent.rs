@@ -0,0 +1,186 @@
+// Serpent with 256 bit keys, byte order as used by TrueCrypt (NESSIE test vectors).
+// The S-boxes are applied bitsliced: bit i of the four words forms the 4 bit input of S-box i.
+
+use cipher::{self, BlockCipher};
+use error::TcFinderError;
+
+const ROUNDS: usize = 32;
+const PHI: u32 = 0x9E37_79B9;
+
+const SBOX: [[u8; 16]; 8] = [
+    [3, 8, 15, 1, 10, 6, 5, 11, 14, 13, 4, 2, 7, 0, 9, 12],
+    [15, 12, 2, 7, 9, 0, 5, 10, 1, 11, 14, 8, 6, 13, 3, 4],
+    [8, 6, 7, 9, 3, 12, 10, 15, 13, 1, 14, 4, 0, 11, 5, 2],
+    [0, 15, 11, 8, 12, 9, 6, 3, 13, 1, 2, 4, 10, 7, 5, 14],
+    [1, 15, 8, 3, 12, 0, 11, 6, 2, 5, 4, 10, 9, 14, 7, 13],
+    [15, 5, 2, 11, 4, 10, 9, 12, 0, 3, 14, 8, 13, 6, 7, 1],
+    [7, 2, 12, 5, 8, 4, 6, 11, 14, 9, 1, 15, 13, 3, 10, 0],
+    [1, 13, 15, 0, 14, 8, 2, 11, 7, 4, 12, 10, 9, 3, 5, 6],
+];
+
+const SBOX_INV: [[u8; 16]; 8] = invert_sboxes();
+
+const fn invert_sboxes() -> [[u8; 16]; 8] {
+    let mut inv = [[0u8; 16]; 8];
+    let mut s = 0;
+    while s < 8 {
+        let mut i = 0;
+        while i < 16 {
+            inv[s][SBOX[s][i] as usize] = i as u8;
+            i += 1;
+        }
+        s += 1;
+    }
+    inv
+}
+
+pub struct Serpent {
+    subkeys: [[u32; 4]; ROUNDS + 1],
+}
+
+impl Serpent {
+    pub fn new(key: &[u8]) -> Serpent {
+        assert!(key.len() == 32, "Serpent: key length != 32");
+
+        // Prekeys w[-8..-1] are the key words, w[i] = (w[i-8] ^ w[i-5] ^ w[i-3] ^ w[i-1] ^ PHI ^ i) <<< 11.
+        let mut w = [0u32; 8 + 4 * (ROUNDS + 1)];
+        for (i, chunk) in key.chunks(4).enumerate() {
+            w[i] = read_u32_le(chunk);
+        }
+        for i in 8..w.len() {
+            w[i] = (w[i - 8] ^ w[i - 5] ^ w[i - 3] ^ w[i - 1] ^ PHI ^ (i - 8) as u32).rotate_left(11);
+        }
+
+        let mut subkeys = [[0u32; 4]; ROUNDS + 1];
+        for (i, subkey) in subkeys.iter_mut().enumerate() {
+            let words = [w[8 + 4 * i], w[9 + 4 * i], w[10 + 4 * i], w[11 + 4 * i]];
+            *subkey = sbox(&SBOX[(ROUNDS + 3 - i) % 8], words);
+        }
+        Serpent { subkeys }
+    }
+}
+
+impl BlockCipher for Serpent {
+    fn encrypt_block(&self, block: &mut [u8]) -> Result<(), TcFinderError> {
+        cipher::check_block(block)?;
+        let mut x = load_block(block);
+        for round in 0..ROUNDS {
+            x = xor_subkey(x, &self.subkeys[round]);
+            x = sbox(&SBOX[round % 8], x);
+            if round < ROUNDS - 1 {
+                x = linear_transform(x);
+            } else {
+                x = xor_subkey(x, &self.subkeys[ROUNDS]);
+            }
+        }
+        store_block(x, block);
+        Ok(())
+    }
+
+    fn decrypt_block(&self, block: &mut [u8]) -> Result<(), TcFinderError> {
+        cipher::check_block(block)?;
+        let mut x = load_block(block);
+        for round in (0..ROUNDS).rev() {
+            if round < ROUNDS - 1 {
+                x = inverse_linear_transform(x);
+            } else {
+                x = xor_subkey(x, &self.subkeys[ROUNDS]);
+            }
+            x = sbox(&SBOX_INV[round % 8], x);
+            x = xor_subkey(x, &self.subkeys[round]);
+        }
+        store_block(x, block);
+        Ok(())
+    }
+}
+
+fn sbox(table: &[u8; 16], x: [u32; 4]) -> [u32; 4] {
+    let mut out = [0u32; 4];
+    for bit in 0..32 {
+        let input = (x[0] >> bit & 1) | (x[1] >> bit & 1) << 1 | (x[2] >> bit & 1) << 2 | (x[3] >> bit & 1) << 3;
+        let output = u32::from(table[input as usize]);
+        for (j, word) in out.iter_mut().enumerate() {
+            *word |= (output >> j & 1) << bit;
+        }
+    }
+    out
+}
+
+fn linear_transform(x: [u32; 4]) -> [u32; 4] {
+    let x0 = x[0].rotate_left(13);
+    let x2 = x[2].rotate_left(3);
+    let x1 = x[1] ^ x0 ^ x2;
+    let x3 = x[3] ^ x2 ^ (x0 << 3);
+    let x1 = x1.rotate_left(1);
+    let x3 = x3.rotate_left(7);
+    let x0 = x0 ^ x1 ^ x3;
+    let x2 = x2 ^ x3 ^ (x1 << 7);
+    [x0.rotate_left(5), x1, x2.rotate_left(22), x3]
+}
+
+fn inverse_linear_transform(x: [u32; 4]) -> [u32; 4] {
+    let x2 = x[2].rotate_right(22);
+    let x0 = x[0].rotate_right(5);
+    let x2 = x2 ^ x[3] ^ (x[1] << 7);
+    let x0 = x0 ^ x[1] ^ x[3];
+    let x3 = x[3].rotate_right(7);
+    let x1 = x[1].rotate_right(1);
+    let x3 = x3 ^ x2 ^ (x0 << 3);
+    let x1 = x1 ^ x0 ^ x2;
+    [x0.rotate_right(13), x1, x2.rotate_right(3), x3]
+}
+
+fn xor_subkey(x: [u32; 4], subkey: &[u32; 4]) -> [u32; 4] {
+    [x[0] ^ subkey[0], x[1] ^ subkey[1], x[2] ^ subkey[2], x[3] ^ subkey[3]]
+}
+
+fn load_block(block: &[u8]) -> [u32; 4] {
+    assert!(block.len() == 16, "Serpent: block length != 16");
+    [read_u32_le(&block[0..4]), read_u32_le(&block[4..8]), read_u32_le(&block[8..12]), read_u32_le(&block[12..16])]
+}
+
+fn store_block(x: [u32; 4], block: &mut [u8]) {
+    for (word, chunk) in x.iter().zip(block.chunks_mut(4)) {
+        for (i, byte) in chunk.iter_mut().enumerate() {
+            *byte = (word >> (i * 8)) as u8;
+        }
+    }
+}
+
+fn read_u32_le(bytes: &[u8]) -> u32 {
+    bytes.iter().rev().fold(0, |acc, &b| acc << 8 | u32::from(b))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::Serpent;
+    use cipher::BlockCipher;
+
+    fn hex_str_to_vec(s: &str) -> Vec<u8> {
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i+2], 16).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_serpent() {
+        // NESSIE Serpent-256 set 3 vector 0 and set 1 vector 0. The last one is not from NESSIE, it was
+        // computed with the Serpent of Nettle.
+        let test_cases = [
+            ("0000000000000000000000000000000000000000000000000000000000000000",
+             "00000000000000000000000000000000", "49672ba898d98df95019180445491089"),
+            ("8000000000000000000000000000000000000000000000000000000000000000",
+             "00000000000000000000000000000000", "a223aa1288463c0e2be38ebd825616c0"),
+            ("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f",
+             "00112233445566778899aabbccddeeff", "2868b7a2d28ecd5e4fdefac3c4330074"),
+        ];
+
+        for &(key, plain, cipher) in &test_cases {
+            let serpent = Serpent::new(&hex_str_to_vec(key));
+            let mut block = hex_str_to_vec(plain);
+            serpent.encrypt_block(&mut block).unwrap();
+            assert_eq!(block, hex_str_to_vec(cipher));
+            serpent.decrypt_block(&mut block).unwrap();
+            assert_eq!(block, hex_str_to_vec(plain));
+        }
+    }
+}
@@ -1,39 +1,179 @@
-use std::fs::File;
+use std::cmp;
 use std::io::prelude::*;
-use std::io::{BufReader, SeekFrom};
+use std::io::SeekFrom;
 
-use crypto::hmac::Hmac;
-use crypto::pbkdf2;
-use crypto::ripemd160::Ripemd160;
-
-use xts;
 use time;
 
 use threadpool::ThreadPool;
 use std::sync::{Arc, Mutex};
+use std::thread;
 
 use pbr::ProgressBar;
 
-use partitioninfo;
+use checkpoint::Checkpoint;
+use cipher::{self, Direction, EncryptionAlgorithm, ExpandedKeys};
+use error::TcFinderError;
+use kdf::{self, KeyDerivation, LazyKeypool, PasswordKey};
+use partitioninfo::{self, PartitionInfo};
+use reader::{ReaderKind, SectorReader};
+use volumeheader::{self, VolumeFormat, VolumeHeader};
 
 const BUFFER_SIZE: usize = 48*1024;
+// Seconds between two checkpoints of a scan.
+const CHECKPOINT_INTERVAL: u64 = 60;
 
 pub struct TCFinder {
-    file: File
+    reader: SectorReader,
+    // Sectors that could not be read, see reader::read_sectors.
+    unreadable_sectors: Vec<u64>,
+    // File and progress saved by scan while run has a checkpoint configured.
+    checkpoint: Option<(String, Checkpoint)>,
+}
+
+// Sector with a header that decrypted successfully and passed the checksums.
+#[derive(Debug, Clone, Copy)]
+pub struct FoundHeader {
+    pub sector: u64,
+    pub header: VolumeHeader,
+    // Key derivation the header was decrypted with.
+    pub derivation: KeyDerivation,
+    pub algorithm: EncryptionAlgorithm,
+    // Index of the matching password in the password list.
+    pub password_index: usize,
+}
+
+// What to search for. passwords are the candidates with keyfiles already applied, see keyfile::KeyfilePool.
+#[derive(Debug, Clone)]
+pub struct ScanConfig {
+    // Inclusive sector ranges searched for primary headers.
+    pub sector_ranges: Vec<(u64, u64)>,
+    pub passwords: Vec<Vec<u8>>,
+    pub derivations: Vec<KeyDerivation>,
+    // Only these encryption algorithms are tried, in every format that supports them.
+    pub algorithms: Vec<EncryptionAlgorithm>,
+    // Check the hidden volume header position inside every volume found.
+    pub hidden: bool,
+    // Check the backup header positions of volumes ending at volume_ends (default: end of the partition).
+    pub backup: bool,
+    pub volume_ends: Vec<u64>,
+    // Save the progress of the sector ranges to this file every minute, see checkpoint::Checkpoint.
+    pub checkpoint: Option<String>,
+    // Continue the scan saved in the checkpoint file instead of starting over.
+    pub resume: bool,
+    // Worker threads checking the sectors of the ranges (default: number of CPUs).
+    pub threads: usize,
+}
+
+impl ScanConfig {
+    // Searches the sector ranges with all encryption algorithms, hidden volumes included, backup headers not.
+    pub fn new(sector_ranges: Vec<(u64, u64)>, passwords: Vec<Vec<u8>>, derivations: Vec<KeyDerivation>) -> ScanConfig {
+        ScanConfig {
+            sector_ranges,
+            passwords,
+            derivations,
+            algorithms: cipher::ALL_ALGORITHMS.to_vec(),
+            hidden: true,
+            backup: false,
+            volume_ends: Vec::new(),
+            checkpoint: None,
+            resume: false,
+            threads: available_threads(),
+        }
+    }
+}
+
+// Threads that run at once on this machine, 4 if that cannot be determined.
+pub fn available_threads() -> usize {
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(4)
+}
+
+#[derive(Debug, Clone)]
+pub struct ScanResult {
+    // Primary headers of the sector ranges, followed by the hidden volume headers.
+    pub headers: Vec<FoundHeader>,
+    pub backup_headers: Vec<FoundHeader>,
+    // Primary headers with the backup header of the same volume, see pair_headers.
+    pub pairs: Vec<(FoundHeader, FoundHeader)>,
+    pub sector_size: u64,
+    // Sectors skipped because they could not be read, in ascending order.
+    pub unreadable_sectors: Vec<u64>,
 }
 
 impl TCFinder {
-    pub fn new(drive_path: &str) -> TCFinder {
-        TCFinder {
-            file: File::open(&drive_path).expect("Opening file failed!")
+    pub fn new(drive_path: &str) -> Result<TCFinder, TcFinderError> {
+        TCFinder::open(drive_path, ReaderKind::Buffered)
+    }
+
+    // Scans with the given reader, see reader::SectorReader.
+    pub fn open(drive_path: &str, reader_kind: ReaderKind) -> Result<TCFinder, TcFinderError> {
+        let reader = SectorReader::open(drive_path, reader_kind).map_err(|e| TcFinderError::io(0, e))?;
+        Ok(TCFinder { reader, unreadable_sectors: Vec::new(), checkpoint: None })
+    }
+
+    // Runs every search of the config: the sector ranges, the hidden volumes inside the volumes found and the
+    // backup headers. When resuming, the headers of the checkpoint are checked again and only the sectors not
+    // completed yet are scanned.
+    pub fn run(&mut self, config: &ScanConfig) -> Result<ScanResult, TcFinderError> {
+        self.unreadable_sectors.clear();
+        let checkpoint = match config.checkpoint {
+            Some(ref path) if config.resume => Checkpoint::load(path, config)?,
+            _ => Checkpoint::new(config),
+        };
+        self.unreadable_sectors.extend_from_slice(&checkpoint.unreadable_sectors);
+
+        let mut headers = Vec::new();
+        if !checkpoint.found_sectors.is_empty() {
+            let sector_size = self.sector_size()?;
+            let keys = kdf::password_keys(&config.passwords, &config.derivations);
+            for &sector in &checkpoint.found_sectors {
+                headers.extend(self.check_sector(&keys, &config.algorithms, sector, sector_size)?);
+            }
+        }
+
+        let remaining = checkpoint.remaining(&config.sector_ranges);
+        self.checkpoint = config.checkpoint.clone().map(|path| (path, checkpoint));
+        let scanned = if !remaining.is_empty() {
+            self.scan(&remaining, config.passwords.clone(), &config.derivations, &config.algorithms, config.threads)
+        } else {
+            Ok(Vec::new())
+        };
+        self.checkpoint = None;
+        headers.extend(scanned?);
+        if config.hidden {
+            let hidden_headers = self.scan_hidden(&headers, &config.sector_ranges, &config.passwords,
+                                                  &config.derivations, &config.algorithms)?;
+            headers.extend(hidden_headers);
         }
+        let backup_headers = if config.backup {
+            self.scan_backup(&config.volume_ends, &config.passwords, &config.derivations, &config.algorithms)?
+        } else {
+            Vec::new()
+        };
+
+        // Backup headers may also lie inside the scanned sector ranges.
+        let sector_size = self.sector_size()?;
+        let all_headers: Vec<FoundHeader> = headers.iter().chain(backup_headers.iter()).cloned().collect();
+        let pairs = pair_headers(&headers, &all_headers, sector_size);
+        let mut unreadable_sectors = self.unreadable_sectors.clone();
+        unreadable_sectors.sort();
+        unreadable_sectors.dedup();
+        Ok(ScanResult { headers, backup_headers, pairs, sector_size, unreadable_sectors })
     }
 
-    pub fn scan(&mut self, sector_ranges: &[(u64, u64)], password: String) -> Vec<u64> {
-        let info = partitioninfo::read_info_ntfs(&mut self.file).unwrap();
+    // Only the given encryption algorithms are tried, in every format that supports them. The sectors are checked
+    // by the given number of threads.
+    pub fn scan(&mut self, sector_ranges: &[(u64, u64)], passwords: Vec<Vec<u8>>, derivations: &[KeyDerivation],
+                algorithms: &[EncryptionAlgorithm], threads: usize) -> Result<Vec<FoundHeader>, TcFinderError> {
+        check_algorithms(derivations, algorithms)?;
+        let info = self.read_partition_info()?;
         println!("{}", info);
 
-        // TODO: Test ranges: negative, > total sectors
+        // The NTFS sector count excludes the backup boot sector, the last sector of the partition.
+        for &(start, end) in sector_ranges {
+            if start > end || end > info.total_sectors_in_partition {
+                return Err(TcFinderError::InvalidRange { start, end });
+            }
+        }
 
         let sector_size: u64 = u64::from(info.bytes_per_sector);
         let job_count: usize = BUFFER_SIZE / sector_size as usize;
@@ -44,67 +184,68 @@ impl TCFinder {
         let mut progressbar = ProgressBar::new(total_sectors_count);
         progressbar.format("╢▌▌░╟");
 
-        // TODO: Fix this..
-        let shared_password = Arc::new(password);
-
-        let mut buf_reader = BufReader::with_capacity(BUFFER_SIZE, &self.file);
-        // Buffer while reading data.
-        let mut buf = [0u8; BUFFER_SIZE];
+        let shared_keys = Arc::new(kdf::password_keys(&passwords, derivations));
+        let shared_algorithms = Arc::new(algorithms.to_vec());
 
         // Vec of all potential headers.
-        let found_sectors: Arc<Mutex<Vec<u64>>> = Arc::new(Mutex::new(Vec::new()));
+        let found_headers: Arc<Mutex<Vec<FoundHeader>>> = Arc::new(Mutex::new(Vec::new()));
 
-        let threadpool = ThreadPool::new(4);
+        let threadpool = ThreadPool::new(cmp::max(threads, 1));
 
         let progressbar = Arc::new(Mutex::new(progressbar));
 
+        // Sectors handed to the threadpool since the last checkpoint.
+        let mut unsaved_ranges = Vec::new();
+        let mut saved_time = scan_start_time;
+
         for &(start_sector, end_sector) in sector_ranges {
             {
                 let mut progressbar_mutex = progressbar.lock().unwrap();
                 progressbar_mutex.message(&format!("[{}-{}]:  ", start_sector, end_sector));
             }
-            let start_bytes = start_sector * sector_size;
-            buf_reader.seek(SeekFrom::Start(start_bytes)).expect("Seeking to start failed!");
             let mut i = start_sector;
+            let mut unsaved_start = start_sector;
 
             while i <= end_sector {
-                buf_reader.read_exact(&mut buf).expect("Filling buffer failed!");
+                // Sector range might not be multiple of buffer size. Stop at end_sector.
+                let sectors_in_buffer = cmp::min(end_sector - i + 1, job_count as u64) as usize;
+                let unreadable_count = self.unreadable_sectors.len();
+                let chunk = self.reader.read_chunk(i, sectors_in_buffer, sector_size, &mut self.unreadable_sectors);
+                for sector in &self.unreadable_sectors[unreadable_count..] {
+                    println!("\n\x1b\x5b1;31;1mUnreadable sector {} skipped.\x1b\x5b1;0m", sector);
+                }
 
-                for j in 0..job_count {
-                    // Sector range might not be multiple of buffer size. Break if over end_sector.
-                    if i + j as u64 > end_sector {break;}
+                // Every job checks a batch of sectors, their key pools are derived together.
+                for batch_start in (0..sectors_in_buffer).step_by(kdf::BATCH_SIZE) {
+                    let batch_end = cmp::min(batch_start + kdf::BATCH_SIZE, sectors_in_buffer);
 
-                    let pass = Arc::clone(&shared_password);
+                    let chunk = chunk.clone();
+                    let keys = Arc::clone(&shared_keys);
+                    let algorithms = Arc::clone(&shared_algorithms);
 
                     let progressbar_arc = progressbar.clone();
-                    let result_vec = found_sectors.clone();
+                    let result_vec = found_headers.clone();
                     threadpool.execute(move || {
-                        // Skip if 00 00 00 00 00 at start, unlikely to be a header.
-                        if buf[j*sector_size as usize..j*sector_size as usize + 5] == [0u8;5] {
-                            progressbar_arc.lock().unwrap().inc();
-                            return;
+                        let mut headers = Vec::new();
+                        for j in batch_start..batch_end {
+                            let header_start = j*sector_size as usize;
+                            // Skip if 00 00 00 00 00 at start, unlikely to be a header.
+                            if chunk[header_start..header_start + 5] == [0u8;5] {
+                                continue;
+                            }
+                            headers.push((i + j as u64, &chunk[header_start..header_start + volumeheader::HEADER_SIZE]));
                         }
 
-                        let mut hmac: Hmac<Ripemd160> = Hmac::new(Ripemd160::new(),
-                                                                  pass.as_bytes());
-                        // First 64 bytes of header is salt.
-                        let salt = &buf[j*sector_size as usize..j*sector_size as usize +64];
-                        // Only need first block (16 bytes) to decrypt magic bytes ("TRUE").
-                        let header =
-                            &buf[j*sector_size as usize + 64..
-                                 j*sector_size as usize + 64 + 16];
-
-                        let result = decrypt(&mut hmac, salt, header);
-
-                        let found = result[..4] == [0x54, 0x52, 0x55, 0x45];
-                        if found {
-                            let mut result_vec = result_vec.lock().unwrap();
-                            result_vec.push(i + j as u64);
-                            println!("\n\x1b\x5b1;32;1mFOUND: {} = {} LBA", i*sector_size, i + j as u64);
-                            println!("Decrypted: {}\x1b\x5b1;0m", arr_as_hex_str(&result));
+                        for (found, result) in check_headers(&keys, &algorithms, &headers) {
+                            let sector = found.sector;
+                            println!("\n\x1b\x5b1;32;1mFOUND: {} = {} LBA ({:?}, {:?}, password #{})",
+                                     sector*sector_size, sector, found.derivation.prf, found.algorithm, found.password_index + 1);
+                            println!("Decrypted: {}", arr_as_hex_str(&result[..16]));
+                            println!("{}\x1b\x5b1;0m", found.header);
+                            result_vec.lock().unwrap().push(found);
                         }
 
-                        progressbar_arc.lock().unwrap().inc();
+                        progressbar_arc.lock().unwrap().add((batch_end - batch_start) as u64);
                     });
 
                     if threadpool.queued_count() > 500 {
@@ -112,24 +253,261 @@ impl TCFinder {
                     }
                 }
 
-                buf_reader.consume(BUFFER_SIZE);
-                i += BUFFER_SIZE as u64 / 512;
+                i += sectors_in_buffer as u64;
+
+                if self.checkpoint.is_some() && time::precise_time_ns() - saved_time > CHECKPOINT_INTERVAL * 1_000_000_000 {
+                    threadpool.join();
+                    unsaved_ranges.push((unsaved_start, i - 1));
+                    unsaved_start = i;
+                    self.save_checkpoint(&mut unsaved_ranges, &found_headers.lock().unwrap())?;
+                    saved_time = time::precise_time_ns();
+                }
+            }
+            if unsaved_start <= end_sector {
+                unsaved_ranges.push((unsaved_start, end_sector));
             }
         }
         threadpool.join();
+        if threadpool.panic_count() > 0 {
+            return Err(TcFinderError::Crypto("Checking headers failed in a worker thread."));
+        }
+        if self.checkpoint.is_some() {
+            self.save_checkpoint(&mut unsaved_ranges, &found_headers.lock().unwrap())?;
+        }
         let scan_end_time = time::precise_time_ns();
         println!("\nDone! Time: {}s", (scan_end_time - scan_start_time) / 1_000_000_000);
 
-        Arc::try_unwrap(found_sectors).unwrap().into_inner().unwrap()
+        Ok(Arc::try_unwrap(found_headers).unwrap().into_inner().unwrap())
+    }
+
+    // Checks the backup header and hidden volume backup header positions of volumes ending at the given sectors (inclusive).
+    // Without volume ends the end of the partition is used.
+    pub fn scan_backup(&mut self, volume_ends: &[u64], passwords: &[Vec<u8>], derivations: &[KeyDerivation],
+                       algorithms: &[EncryptionAlgorithm]) -> Result<Vec<FoundHeader>, TcFinderError> {
+        check_algorithms(derivations, algorithms)?;
+        let info = self.read_partition_info()?;
+        let sector_size = u64::from(info.bytes_per_sector);
+
+        let volume_ends = if volume_ends.is_empty() {
+            vec![info.total_sectors_in_partition]
+        } else {
+            volume_ends.to_vec()
+        };
+
+        let keys = kdf::password_keys(passwords, derivations);
+        let mut found_headers = Vec::new();
+        for &volume_end in &volume_ends {
+            let sector = match backup_header_sector(volume_end, sector_size) {
+                Some(sector) => sector,
+                None => {
+                    println!("Volume end {} is too small for a backup header.", volume_end);
+                    continue;
+                }
+            };
+
+            for &sector in &[sector, sector + HIDDEN_HEADER_OFFSET / sector_size] {
+                if let Some(found) = self.check_sector(&keys, algorithms, sector, sector_size)? {
+                    println!("\x1b\x5b1;32;1mFOUND BACKUP: {} = {} LBA ({:?}, {:?})",
+                             sector*sector_size, sector, found.derivation.prf, found.algorithm);
+                    println!("{}\x1b\x5b1;0m", found.header);
+                    found_headers.push(found);
+                }
+            }
+        }
+        Ok(found_headers)
+    }
+
+    // Checks the hidden volume header position inside the volumes of the given (outer) headers.
+    // Positions inside the already scanned sector ranges are not checked again.
+    pub fn scan_hidden(&mut self, outer_headers: &[FoundHeader], scanned_ranges: &[(u64, u64)], passwords: &[Vec<u8>],
+                       derivations: &[KeyDerivation], algorithms: &[EncryptionAlgorithm])
+                       -> Result<Vec<FoundHeader>, TcFinderError> {
+        check_algorithms(derivations, algorithms)?;
+        let info = self.read_partition_info()?;
+        let sector_size = u64::from(info.bytes_per_sector);
+
+        let keys = kdf::password_keys(passwords, derivations);
+        let mut found_headers = Vec::new();
+        for outer in outer_headers.iter().filter(|found| !found.header.is_hidden()) {
+            let sector = outer.sector + HIDDEN_HEADER_OFFSET / sector_size;
+            // A volume at the end of the partition has no room for a hidden volume.
+            if sector > info.total_sectors_in_partition
+               || scanned_ranges.iter().any(|&(start, end)| start <= sector && sector <= end) {
+                continue;
+            }
+
+            if let Some(found) = self.check_sector(&keys, algorithms, sector, sector_size)? {
+                println!("\x1b\x5b1;32;1mFOUND HIDDEN: {} = {} LBA ({:?}, {:?})",
+                         sector*sector_size, sector, found.derivation.prf, found.algorithm);
+                println!("{}\x1b\x5b1;0m", found.header);
+                found_headers.push(found);
+            }
+        }
+        Ok(found_headers)
+    }
+
+    // Adds the sector ranges whose jobs are done and the headers found in them to the checkpoint and saves it.
+    fn save_checkpoint(&mut self, done_ranges: &mut Vec<(u64, u64)>, found_headers: &[FoundHeader]) -> Result<(), TcFinderError> {
+        let (ref path, ref mut checkpoint) = *self.checkpoint.as_mut().unwrap();
+        for (start, end) in done_ranges.drain(..) {
+            checkpoint.add_completed(start, end);
+        }
+        for found in found_headers {
+            if !checkpoint.found_sectors.contains(&found.sector) {
+                checkpoint.found_sectors.push(found.sector);
+            }
+        }
+        let mut unreadable_sectors = self.unreadable_sectors.clone();
+        unreadable_sectors.sort();
+        unreadable_sectors.dedup();
+        checkpoint.unreadable_sectors = unreadable_sectors;
+        checkpoint.save(path)
+    }
+
+    // Sectors skipped so far because they could not be read.
+    pub fn unreadable_sectors(&self) -> &[u64] {
+        &self.unreadable_sectors
+    }
+
+    pub fn sector_size(&mut self) -> Result<u64, TcFinderError> {
+        Ok(u64::from(self.read_partition_info()?.bytes_per_sector))
+    }
+
+    fn check_sector(&mut self, keys: &[Vec<PasswordKey>], algorithms: &[EncryptionAlgorithm], sector: u64, sector_size: u64)
+                    -> Result<Option<FoundHeader>, TcFinderError> {
+        let mut header = [0u8; volumeheader::HEADER_SIZE];
+        if !self.reader.read_sectors(sector, sector_size, &mut header, &mut self.unreadable_sectors) {
+            println!("\x1b\x5b1;31;1mUnreadable sector {} skipped.\x1b\x5b1;0m", sector);
+            return Ok(None);
+        }
+
+        Ok(check_header(keys, algorithms, sector, &header).map(|(found, _)| found))
+    }
+
+    pub fn read_partition_info(&mut self) -> Result<PartitionInfo, TcFinderError> {
+        let file = self.reader.file();
+        file.seek(SeekFrom::Start(0)).map_err(|e| TcFinderError::io(0, e))?;
+        partitioninfo::read_info_ntfs(file)
     }
 }
 
-fn decrypt(hmac: &mut Hmac<Ripemd160>, salt: &[u8], header: &[u8]) -> [u8; 16] {
-    let mut header_keypool = [0u8; 64];
-    pbkdf2::pbkdf2(hmac, salt, 2000, &mut header_keypool);
-    let key1 = &header_keypool[..32];
-    let key2 = &header_keypool[32..];
-    xts::xts_decrypt(key1, key2, header)
+// Size of the header area at the start and at the end of a volume (TrueCrypt 6.0+).
+// The primary header area holds the normal and the hidden volume header, the area at the end their backups.
+const HEADER_AREA_SIZE: u64 = 128 * 1024;
+// Offset of the hidden volume header inside its header area.
+const HIDDEN_HEADER_OFFSET: u64 = 64 * 1024;
+
+// Sector of the backup header of a volume whose last sector is volume_end.
+pub fn backup_header_sector(volume_end: u64, sector_size: u64) -> Option<u64> {
+    (volume_end + 1).checked_sub(HEADER_AREA_SIZE / sector_size)
+}
+
+// Pairs primary and backup headers that agree on the volume size and lie exactly one volume apart.
+// Hidden volume headers are not paired, their position depends on the size of the outer volume.
+pub fn pair_headers(primary: &[FoundHeader], backup: &[FoundHeader], sector_size: u64) -> Vec<(FoundHeader, FoundHeader)> {
+    let mut pairs = Vec::new();
+    for p in primary.iter().filter(|found| !found.header.is_hidden()) {
+        let expected_backup = p.sector + (HEADER_AREA_SIZE + p.header.volume_size) / sector_size;
+        for b in backup {
+            if b.sector == expected_backup && b.header.volume_size == p.header.volume_size && !b.header.is_hidden() {
+                pairs.push((*p, *b));
+            }
+        }
+    }
+    pairs
+}
+
+// Tries every password with every key derivation and every given encryption algorithm its format supports on
+// the header at the given sector. keys are the passwords prepared for the key derivations, see kdf::password_keys.
+// Returns the first combination that decrypts it and the decrypted bytes.
+pub fn check_header(keys: &[Vec<PasswordKey>], algorithms: &[EncryptionAlgorithm], sector: u64, header: &[u8])
+                    -> Option<(FoundHeader, Vec<u8>)> {
+    for (password_index, password_keys) in keys.iter().enumerate() {
+        for key in password_keys {
+            let derivation = key.derivation;
+            // Only as much of the key pool is derived as the algorithms tried so far need.
+            let mut keypool = LazyKeypool::new(key, &header[..volumeheader::SALT_SIZE]);
+            for algorithm in tried_algorithms(derivation, algorithms) {
+                if let Some((volume_header, result)) = decrypt_header(keypool.get(algorithm.key_size()), algorithm, derivation, header) {
+                    return Some((FoundHeader { sector, header: volume_header, derivation, algorithm, password_index }, result));
+                }
+            }
+        }
+    }
+    None
+}
+
+// Like check_header for several (sector, header) pairs. The key pools of all headers are derived in one batch,
+// see PasswordKey::derive_keys. Returns the headers that decrypted with the decrypted bytes.
+pub fn check_headers(keys: &[Vec<PasswordKey>], algorithms: &[EncryptionAlgorithm], headers: &[(u64, &[u8])])
+                     -> Vec<(FoundHeader, Vec<u8>)> {
+    let mut results: Vec<Option<(FoundHeader, Vec<u8>)>> = vec![None; headers.len()];
+    for (password_index, password_keys) in keys.iter().enumerate() {
+        for key in password_keys {
+            let derivation = key.derivation;
+            let tried = tried_algorithms(derivation, algorithms);
+            // Headers that already decrypted are not tried again.
+            let pending: Vec<usize> = (0..headers.len()).filter(|&i| results[i].is_none()).collect();
+            let length = match tried.iter().map(|algorithm| algorithm.key_size()).max() {
+                Some(length) if !pending.is_empty() => length,
+                _ => continue,
+            };
+
+            let salts: Vec<&[u8]> = pending.iter().map(|&i| &headers[i].1[..volumeheader::SALT_SIZE]).collect();
+            for (&i, keypool) in pending.iter().zip(key.derive_keys(&salts, length)) {
+                let (sector, header) = headers[i];
+                for &algorithm in &tried {
+                    if let Some((volume_header, result)) = decrypt_header(&keypool, algorithm, derivation, header) {
+                        results[i] = Some((FoundHeader { sector, header: volume_header, derivation, algorithm, password_index }, result));
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    results.into_iter().flatten().collect()
+}
+
+// Fails if no key derivation supports any of the algorithms, nothing could decrypt.
+fn check_algorithms(derivations: &[KeyDerivation], algorithms: &[EncryptionAlgorithm]) -> Result<(), TcFinderError> {
+    if derivations.iter().all(|&derivation| tried_algorithms(derivation, algorithms).is_empty()) {
+        return Err(TcFinderError::Crypto("The key derivations do not support any of the encryption algorithms."));
+    }
+    Ok(())
+}
+
+// The given algorithms the format of the key derivation supports.
+fn tried_algorithms(derivation: KeyDerivation, algorithms: &[EncryptionAlgorithm]) -> Vec<EncryptionAlgorithm> {
+    cipher::algorithms(derivation.format).iter().cloned().filter(|algorithm| algorithms.contains(algorithm)).collect()
+}
+
+// Decrypts the header with the algorithm, returns the parsed header and the decrypted bytes if it is valid.
+fn decrypt_header(keypool: &[u8], algorithm: EncryptionAlgorithm, derivation: KeyDerivation, header: &[u8])
+                  -> Option<(VolumeHeader, Vec<u8>)> {
+    // The keys are expanded once for the fast reject and the full decryption.
+    let keys = algorithm.expand_keys(keypool, Direction::Decrypt).ok()?;
+    if !magic_matches(&keys, derivation.format, header) {
+        return None;
+    }
+    let result = decrypt(&keys, &header[volumeheader::SALT_SIZE..]).ok()?;
+    match VolumeHeader::parse(&result) {
+        Ok(volume_header) if volume_header.format == derivation.format => Some((volume_header, result)),
+        _ => None,
+    }
+}
+
+// Fast reject: XTS decrypts every block on its own, so only the first block with the magic bytes is decrypted.
+// Nearly all wrong keys fail here, the full 448 bytes are decrypted and checked for the rest.
+fn magic_matches(keys: &ExpandedKeys, format: VolumeFormat, header: &[u8]) -> bool {
+    let mut block = [0u8; cipher::BLOCK_SIZE];
+    block.copy_from_slice(&header[volumeheader::SALT_SIZE..volumeheader::SALT_SIZE + cipher::BLOCK_SIZE]);
+    keys.decrypt_data_unit(0, &mut block).is_ok() && block[..4] == format.magic()
+}
+
+fn decrypt(keys: &ExpandedKeys, encrypted: &[u8]) -> Result<Vec<u8>, TcFinderError> {
+    let mut decrypted = encrypted.to_vec();
+    keys.decrypt_data_unit(0, &mut decrypted)?;
+    Ok(decrypted)
 }
 
 fn arr_as_hex_str(arr: &[u8]) -> String {
@@ -143,9 +521,16 @@ fn count_total_sectors(sector_ranges: &[(u64, u64)]) -> u64 {
 
 #[cfg(test)]
 mod tests {
-    use crypto::hmac::Hmac;
-    use crypto::ripemd160::Ripemd160;
+    use std::{env, fs};
+
+    use super::{ScanConfig, TCFinder};
+    use checkpoint::Checkpoint;
+    use cipher::{Direction, EncryptionAlgorithm, ALL_ALGORITHMS};
+    use error::TcFinderError;
+    use kdf::{self, KeyDerivation, LazyKeypool, PasswordKey, Prf};
+    #[cfg(feature = "nightly")]
     use test::Bencher;
+    use volumeheader::{VolumeFormat, VolumeHeader};
 
     const TC_HEADER: [u8; 512] =
         [0x3a, 0x6b, 0x85, 0xaf, 0x49, 0xc2, 0x40, 0x1b, 0x77, 0x21, 0x33, 0xc3, 0x31, 0x1b, 0xa8, 0xe9,
@@ -182,18 +567,384 @@ mod tests {
          0xf4, 0x46, 0x58, 0xc5, 0x2f, 0xbb, 0x7b, 0x32, 0xa8, 0xe9, 0xd3, 0x18, 0x45, 0x3e, 0x1c, 0x0d];
 
 
+    // VeraCrypt volume, password "p4ssword", PIM 1.
+    const VC_HEADER_PIM_1: [u8; 512] =
+        [0x82, 0xb7, 0x0e, 0xee, 0x7f, 0x1a, 0x50, 0x39, 0xbe, 0xf0, 0x7e, 0xc2, 0x34, 0x7f, 0x06, 0x6e,
+         0xd0, 0x8f, 0x5d, 0xc7, 0x51, 0x24, 0x47, 0xe3, 0x40, 0x43, 0x00, 0x02, 0x6b, 0x6e, 0x54, 0x55,
+         0x94, 0xa0, 0x65, 0x68, 0x5d, 0x64, 0xc4, 0x98, 0x0b, 0xb8, 0xd4, 0x54, 0x4a, 0x87, 0x21, 0xa9,
+         0x9a, 0x01, 0xad, 0x21, 0x9e, 0xb5, 0x9c, 0xf6, 0xa1, 0x5e, 0xf6, 0xf1, 0x5a, 0x1d, 0x83, 0x0b,
+         0x23, 0x39, 0x2c, 0x42, 0xa8, 0xd9, 0x7a, 0x9c, 0x3e, 0x27, 0x74, 0xce, 0x58, 0x63, 0x03, 0xb0,
+         0x66, 0xfb, 0x33, 0x1a, 0xa5, 0x9b, 0x55, 0xde, 0xe6, 0xdf, 0xce, 0x71, 0xd0, 0xb3, 0xa8, 0xfc,
+         0xcc, 0x5d, 0x01, 0x25, 0xfa, 0xa8, 0x36, 0xa6, 0x4e, 0x9c, 0x58, 0xb6, 0x1d, 0x4c, 0x5e, 0x51,
+         0x35, 0xab, 0x8c, 0xd9, 0x5e, 0x25, 0xb1, 0xa3, 0xf9, 0x37, 0x1b, 0x4e, 0x30, 0xca, 0xa7, 0xa4,
+         0xa1, 0x59, 0x0f, 0x9b, 0xe9, 0x54, 0x3d, 0x37, 0x98, 0x03, 0xf0, 0x11, 0xb0, 0xdd, 0xc5, 0x3c,
+         0x5c, 0xa3, 0xa5, 0x83, 0x11, 0x87, 0x38, 0x7b, 0x38, 0xc0, 0x8b, 0x0d, 0x33, 0x14, 0xea, 0xb7,
+         0x8d, 0xca, 0x92, 0xf0, 0x1b, 0x69, 0xb6, 0xfa, 0x1f, 0x7e, 0xa7, 0x85, 0x7d, 0x6c, 0x59, 0x74,
+         0x13, 0x1c, 0xd7, 0x65, 0xf2, 0xb1, 0xe4, 0xde, 0x3f, 0xea, 0x5c, 0xe3, 0x00, 0x3f, 0xe1, 0xc5,
+         0x3a, 0x95, 0xa0, 0x69, 0x70, 0x59, 0x0d, 0x05, 0x05, 0x27, 0xbb, 0x51, 0xcd, 0xab, 0x6b, 0x75,
+         0xce, 0xbe, 0xed, 0x8a, 0x55, 0xb0, 0xcf, 0x1e, 0x58, 0x8a, 0x67, 0xf3, 0x09, 0x94, 0xeb, 0x88,
+         0xfb, 0x8f, 0x21, 0x7c, 0xb2, 0xae, 0xdb, 0x88, 0x2f, 0xc1, 0xde, 0xab, 0x1a, 0xef, 0x28, 0x46,
+         0x9b, 0x23, 0x46, 0xd0, 0x2a, 0xd9, 0xe0, 0x28, 0x15, 0x8e, 0x8b, 0x08, 0x50, 0x6c, 0x69, 0xfd,
+         0x50, 0x80, 0x81, 0x58, 0xd2, 0x31, 0x9e, 0x64, 0xb9, 0x90, 0x62, 0x06, 0x65, 0x20, 0x97, 0xc7,
+         0xc0, 0x67, 0x35, 0x79, 0x0e, 0x66, 0x44, 0x9e, 0xad, 0x46, 0x27, 0x28, 0x9b, 0x25, 0x0e, 0x41,
+         0xc9, 0x8a, 0x2e, 0x27, 0xa4, 0xb2, 0x6e, 0x58, 0x8a, 0x12, 0x81, 0x66, 0x2e, 0x06, 0xa9, 0xa3,
+         0x4e, 0x2f, 0x2d, 0x6a, 0xd2, 0x8f, 0x5e, 0x01, 0xb6, 0x0e, 0x7d, 0xdf, 0x96, 0x7e, 0x31, 0x54,
+         0x5d, 0x6f, 0x06, 0x52, 0x80, 0x86, 0xbc, 0xa7, 0xee, 0xae, 0x12, 0xc4, 0x38, 0x06, 0xe4, 0xa1,
+         0x84, 0x0c, 0x44, 0x04, 0xe8, 0xca, 0xc6, 0x67, 0xc0, 0xcf, 0x53, 0x1a, 0x7b, 0x2b, 0x0a, 0x70,
+         0x55, 0x42, 0x2f, 0x2a, 0x17, 0x3a, 0xa3, 0x98, 0x49, 0x27, 0x34, 0xac, 0x20, 0xa8, 0xec, 0xe6,
+         0x2f, 0xf5, 0xe9, 0xc1, 0x85, 0x61, 0x48, 0x78, 0x91, 0xc1, 0x9a, 0x37, 0x38, 0x24, 0x77, 0x47,
+         0x57, 0x32, 0x2c, 0x22, 0x7d, 0x31, 0x00, 0x4d, 0xc6, 0x5d, 0xbd, 0x78, 0x11, 0xe7, 0x7b, 0xd0,
+         0xfa, 0x55, 0x1e, 0x02, 0x1c, 0xb1, 0xaa, 0x08, 0x74, 0x9e, 0xac, 0xfb, 0x33, 0x39, 0x66, 0x9a,
+         0x29, 0x9d, 0x73, 0xbd, 0x27, 0xc0, 0xeb, 0xe7, 0x94, 0x69, 0x7a, 0xaa, 0xdd, 0x77, 0x5d, 0xe7,
+         0xab, 0xfc, 0x30, 0x67, 0xfe, 0x6c, 0xdf, 0x9d, 0x2a, 0xc9, 0x29, 0xee, 0xa1, 0x4d, 0xf4, 0xdf,
+         0xe0, 0xe5, 0x9f, 0xfb, 0x60, 0xa8, 0xc9, 0xf2, 0x7d, 0x92, 0xe2, 0xcc, 0x94, 0x59, 0xa1, 0x44,
+         0x31, 0x2b, 0xbd, 0xc3, 0xf7, 0xd9, 0x33, 0x2f, 0x78, 0xd7, 0x91, 0x4e, 0x20, 0x93, 0xa3, 0x19,
+         0xdb, 0xee, 0x98, 0x7b, 0x3b, 0x5a, 0x15, 0x02, 0xda, 0xb3, 0x77, 0xe8, 0xa9, 0xc5, 0xc5, 0x28,
+         0x6d, 0x0a, 0xb3, 0x44, 0xc6, 0x11, 0x4c, 0xf1, 0xf0, 0x76, 0xef, 0xa7, 0x02, 0x53, 0xfe, 0x8f];
+
+
     #[test]
     fn test_decrypt() {
         let password = "p4ssword";
-        let mut hmac: Hmac<Ripemd160> = Hmac::new(Ripemd160::new(), password.as_bytes());
-        let decrypted_bytes = super::decrypt(&mut hmac, &TC_HEADER[..64], &TC_HEADER[64..64 +16]);
+        let derivation = KeyDerivation::new(VolumeFormat::TrueCrypt, Prf::Ripemd160, None, false).unwrap();
+        let key = PasswordKey::new(password.as_bytes(), derivation);
+        let decrypted_bytes = super::decrypt(&EncryptionAlgorithm::Aes.expand_keys(LazyKeypool::new(&key, &TC_HEADER[..64]).get(64), Direction::Decrypt).unwrap(), &TC_HEADER[64..]).unwrap();
         assert_eq!(decrypted_bytes[..4], [0x54, 0x52, 0x55, 0x45]);
+
+        let header = VolumeHeader::parse(&decrypted_bytes).unwrap();
+        assert_eq!(header.version, 5);
+        assert_eq!(header.required_program_version, 0x0700);
+        assert_eq!(header.key_area_crc, 0xD1F8_6BF7);
+        assert_eq!(header.hidden_volume_size, 0);
+        assert_eq!(header.volume_size, 20_709_376);
+        assert_eq!(header.master_key_scope_offset, 131_072);
+        assert_eq!(header.master_key_scope_size, 20_709_376);
+        assert_eq!(header.flags, 0);
+        assert_eq!(header.sector_size, 512);
+    }
+
+    #[test]
+    fn test_decrypt_corrupted_key_area() {
+        let password = "p4ssword";
+        let derivation = KeyDerivation::new(VolumeFormat::TrueCrypt, Prf::Ripemd160, None, false).unwrap();
+        let mut corrupted = TC_HEADER;
+        corrupted[300] ^= 1;
+        let key = PasswordKey::new(password.as_bytes(), derivation);
+        let decrypted_bytes = super::decrypt(&EncryptionAlgorithm::Aes.expand_keys(LazyKeypool::new(&key, &corrupted[..64]).get(64), Direction::Decrypt).unwrap(), &corrupted[64..]).unwrap();
+        assert_eq!(decrypted_bytes[..4], [0x54, 0x52, 0x55, 0x45]);
+        assert!(VolumeHeader::parse(&decrypted_bytes).is_err());
+    }
+
+    #[test]
+    fn test_decrypt_wrong_password() {
+        let password = "password";
+        let derivation = KeyDerivation::new(VolumeFormat::TrueCrypt, Prf::Ripemd160, None, false).unwrap();
+        let key = PasswordKey::new(password.as_bytes(), derivation);
+        let decrypted_bytes = super::decrypt(&EncryptionAlgorithm::Aes.expand_keys(LazyKeypool::new(&key, &TC_HEADER[..64]).get(64), Direction::Decrypt).unwrap(), &TC_HEADER[64..]).unwrap();
+        assert!(VolumeHeader::parse(&decrypted_bytes).is_err());
+    }
+
+    #[test]
+    fn test_fast_reject() {
+        let derivation = KeyDerivation::new(VolumeFormat::TrueCrypt, Prf::Ripemd160, None, false).unwrap();
+        let key = PasswordKey::new(b"p4ssword", derivation);
+        let wrong_key = PasswordKey::new(b"password", derivation);
+        let mut corrupted = TC_HEADER;
+        corrupted[300] ^= 1;
+
+        let keypool = LazyKeypool::new(&key, &TC_HEADER[..64]).get(64).to_vec();
+        let keys = EncryptionAlgorithm::Aes.expand_keys(&keypool, Direction::Decrypt).unwrap();
+        assert!(super::magic_matches(&keys, VolumeFormat::TrueCrypt, &TC_HEADER));
+        assert!(!super::magic_matches(&keys, VolumeFormat::VeraCrypt, &TC_HEADER));
+        assert!(super::decrypt_header(&keypool, EncryptionAlgorithm::Aes, derivation, &TC_HEADER).is_some());
+        // The magic bytes still match, the checksum does not.
+        assert!(super::magic_matches(&keys, VolumeFormat::TrueCrypt, &corrupted));
+        assert!(super::decrypt_header(&keypool, EncryptionAlgorithm::Aes, derivation, &corrupted).is_none());
+
+        let keypool = LazyKeypool::new(&wrong_key, &TC_HEADER[..64]).get(64).to_vec();
+        let keys = EncryptionAlgorithm::Aes.expand_keys(&keypool, Direction::Decrypt).unwrap();
+        assert!(!super::magic_matches(&keys, VolumeFormat::TrueCrypt, &TC_HEADER));
+        assert!(super::decrypt_header(&keypool, EncryptionAlgorithm::Aes, derivation, &TC_HEADER).is_none());
+    }
+
+    #[test]
+    fn test_check_header_formats() {
+        let passwords = [b"p4ssword".to_vec()];
+        let truecrypt = KeyDerivation::new(VolumeFormat::TrueCrypt, Prf::Ripemd160, None, false).unwrap();
+        let veracrypt = KeyDerivation::new(VolumeFormat::VeraCrypt, Prf::Ripemd160, Some(1), false).unwrap();
+        let sha512 = KeyDerivation::new(VolumeFormat::VeraCrypt, Prf::Sha512, Some(1), false).unwrap();
+
+        let keys = kdf::password_keys(&passwords, &[truecrypt, veracrypt]);
+        let (found, _) = super::check_header(&keys, &ALL_ALGORITHMS, 0, &TC_HEADER).unwrap();
+        assert_eq!(found.header.format, VolumeFormat::TrueCrypt);
+        assert_eq!(found.derivation, truecrypt);
+        assert_eq!(found.algorithm, EncryptionAlgorithm::Aes);
+
+        let keys = kdf::password_keys(&passwords, &[sha512, truecrypt, veracrypt]);
+        let (found, _) = super::check_header(&keys, &ALL_ALGORITHMS, 0, &VC_HEADER_PIM_1).unwrap();
+        assert_eq!(found.header.format, VolumeFormat::VeraCrypt);
+        assert_eq!(found.derivation, veracrypt);
+        assert_eq!(found.header.required_program_version, 0x010B);
+        assert_eq!(found.header.volume_size, 20_709_376);
+
+        assert!(super::check_header(&kdf::password_keys(&passwords, &[truecrypt, sha512]), &ALL_ALGORITHMS, 0, &VC_HEADER_PIM_1).is_none());
+        assert!(super::check_header(&kdf::password_keys(&passwords, &[veracrypt]), &ALL_ALGORITHMS, 0, &TC_HEADER).is_none());
+    }
+
+    #[test]
+    fn test_check_header_passwords() {
+        let passwords = [b"password".to_vec(), b"p4ssword".to_vec(), b"p4ssw0rd".to_vec()];
+        let derivation = KeyDerivation::new(VolumeFormat::TrueCrypt, Prf::Ripemd160, None, false).unwrap();
+
+        let keys = kdf::password_keys(&passwords, &[derivation]);
+        let (found, _) = super::check_header(&keys, &ALL_ALGORITHMS, 0, &TC_HEADER).unwrap();
+        assert_eq!(found.password_index, 1);
+        assert!(super::check_header(&keys[..1], &ALL_ALGORITHMS, 0, &TC_HEADER).is_none());
+        // The volume is encrypted with AES.
+        let algorithms = [EncryptionAlgorithm::Serpent, EncryptionAlgorithm::AesTwofish];
+        assert!(super::check_header(&keys, &algorithms, 0, &TC_HEADER).is_none());
+        assert!(super::check_header(&keys, &[EncryptionAlgorithm::Aes], 0, &TC_HEADER).is_some());
+    }
+
+    #[test]
+    fn test_check_headers() {
+        let passwords = [b"password".to_vec(), b"p4ssword".to_vec()];
+        let derivation = KeyDerivation::new(VolumeFormat::TrueCrypt, Prf::Ripemd160, None, false).unwrap();
+        let keys = kdf::password_keys(&passwords, &[derivation]);
+
+        let mut corrupted = TC_HEADER;
+        corrupted[300] ^= 1;
+        let headers: [(u64, &[u8]); 3] = [(1, &TC_HEADER), (2, &corrupted), (3, &TC_HEADER)];
+        let results = super::check_headers(&keys, &[EncryptionAlgorithm::Aes], &headers);
+        let sectors: Vec<u64> = results.iter().map(|&(found, _)| found.sector).collect();
+        assert_eq!(sectors, [1, 3]);
+        assert_eq!(results[0].0.password_index, 1);
+        assert_eq!(results[0].0.algorithm, EncryptionAlgorithm::Aes);
+        assert_eq!(results[0].1, super::check_header(&keys, &ALL_ALGORITHMS, 1, &TC_HEADER).unwrap().1);
+
+        assert!(super::check_headers(&keys, &[EncryptionAlgorithm::Serpent], &headers).is_empty());
     }
 
+    // NTFS partition with 200 sectors of 512 bytes, followed by the backup boot sector.
+    fn ntfs_image() -> Vec<u8> {
+        let mut image = vec![0u8; 201 * 512];
+        image[3..11].copy_from_slice(b"NTFS    ");
+        image[0x0B..0x0D].copy_from_slice(&512u16.to_le_bytes());
+        image[0x28..0x30].copy_from_slice(&200u64.to_le_bytes());
+        image
+    }
+
+    #[test]
+    fn test_run() {
+        let mut image = ntfs_image();
+        image[20 * 512..21 * 512].copy_from_slice(&TC_HEADER);
+        let path = env::temp_dir().join("tcfinder_test_run.img");
+        fs::write(&path, &image).unwrap();
+
+        let derivation = KeyDerivation::new(VolumeFormat::TrueCrypt, Prf::Ripemd160, None, false).unwrap();
+        let config = ScanConfig {
+            algorithms: vec![EncryptionAlgorithm::Aes],
+            ..ScanConfig::new(vec![(10, 30)], vec![b"password".to_vec(), b"p4ssword".to_vec()], vec![derivation])
+        };
+        let result = TCFinder::new(path.to_str().unwrap()).unwrap().run(&config).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(result.sector_size, 512);
+        assert_eq!(result.headers.len(), 1);
+        assert_eq!(result.headers[0].sector, 20);
+        assert_eq!(result.headers[0].password_index, 1);
+        assert_eq!(result.headers[0].header.volume_size, 20_709_376);
+        assert!(result.backup_headers.is_empty());
+        assert!(result.pairs.is_empty());
+    }
+
+    #[test]
+    fn test_run_checkpoint() {
+        let mut image = ntfs_image();
+        image[20 * 512..21 * 512].copy_from_slice(&TC_HEADER);
+        image[150 * 512..151 * 512].copy_from_slice(&TC_HEADER);
+        let path = env::temp_dir().join("tcfinder_test_run_checkpoint.img");
+        fs::write(&path, &image).unwrap();
+        let checkpoint_path = env::temp_dir().join("tcfinder_test_run_checkpoint");
+        let checkpoint_path = checkpoint_path.to_str().unwrap().to_string();
+
+        let derivation = KeyDerivation::new(VolumeFormat::TrueCrypt, Prf::Ripemd160, None, false).unwrap();
+        let config = ScanConfig {
+            algorithms: vec![EncryptionAlgorithm::Aes],
+            hidden: false,
+            checkpoint: Some(checkpoint_path.clone()),
+            ..ScanConfig::new(vec![(10, 30), (140, 160)], vec![b"p4ssword".to_vec()], vec![derivation])
+        };
+        let run = |config: &ScanConfig| TCFinder::new(path.to_str().unwrap()).unwrap().run(config);
+
+        let result = run(&config).unwrap();
+        assert_eq!(result.headers.len(), 2);
+        let checkpoint = Checkpoint::load(&checkpoint_path, &config).unwrap();
+        assert_eq!(checkpoint.completed, [(10, 30), (140, 160)]);
+        let mut found_sectors = checkpoint.found_sectors.clone();
+        found_sectors.sort();
+        assert_eq!(found_sectors, [20, 150]);
+
+        // Interrupted after the first range: its header is checked again, the rest is scanned.
+        let mut interrupted = Checkpoint::new(&config);
+        interrupted.add_completed(10, 30);
+        interrupted.found_sectors = vec![20];
+        interrupted.unreadable_sectors = vec![25];
+        interrupted.save(&checkpoint_path).unwrap();
+        let resume = ScanConfig { resume: true, ..config.clone() };
+        let result = run(&resume).unwrap();
+        let mut sectors: Vec<u64> = result.headers.iter().map(|found| found.sector).collect();
+        sectors.sort();
+        assert_eq!(sectors, [20, 150]);
+        assert_eq!(result.unreadable_sectors, [25]);
+
+        // Without the found sector in the checkpoint the header of the completed range is not found again.
+        interrupted.found_sectors.clear();
+        interrupted.save(&checkpoint_path).unwrap();
+        let result = run(&resume).unwrap();
+        assert_eq!(result.headers.len(), 1);
+        assert_eq!(result.headers[0].sector, 150);
+        assert_eq!(Checkpoint::load(&checkpoint_path, &config).unwrap().completed, [(10, 30), (140, 160)]);
+
+        // A checkpoint of different ranges is refused.
+        let other = ScanConfig { sector_ranges: vec![(10, 30)], ..resume.clone() };
+        match run(&other) {
+            Err(TcFinderError::Checkpoint(_)) => (),
+            other => panic!("{:?}", other),
+        }
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&checkpoint_path).unwrap();
+    }
+
+    #[test]
+    fn test_run_errors() {
+        let derivation = KeyDerivation::new(VolumeFormat::TrueCrypt, Prf::Ripemd160, None, false).unwrap();
+        let config = |ranges: Vec<(u64, u64)>| ScanConfig {
+            algorithms: vec![EncryptionAlgorithm::Aes],
+            ..ScanConfig::new(ranges, vec![b"p4ssword".to_vec()], vec![derivation])
+        };
+        let path = env::temp_dir().join("tcfinder_test_run_errors.img");
+        let _ = fs::remove_file(&path);
+        let run = |config: &ScanConfig| TCFinder::new(path.to_str().unwrap()).and_then(|mut tc| tc.run(config));
+
+        match run(&config(vec![(10, 30)])) {
+            Err(TcFinderError::Io { offset: 0, .. }) => (),
+            other => panic!("{:?}", other),
+        }
+
+        fs::write(&path, vec![0u8; 201 * 512]).unwrap();
+        match run(&config(vec![(10, 30)])) {
+            Err(TcFinderError::UnsupportedFilesystem(_)) => (),
+            other => panic!("{:?}", other),
+        }
+
+        for &bytes_per_sector in &[0u16, 0xFFFF] {
+            let mut image = ntfs_image();
+            image[0x0B..0x0D].copy_from_slice(&bytes_per_sector.to_le_bytes());
+            fs::write(&path, &image).unwrap();
+            match run(&config(vec![(10, 30)])) {
+                Err(TcFinderError::UnsupportedFilesystem(_)) => (),
+                other => panic!("{} {:?}", bytes_per_sector, other),
+            }
+        }
+
+        let mut image = ntfs_image();
+        image[200 * 512..].copy_from_slice(&TC_HEADER);
+        fs::write(&path, &image).unwrap();
+        match run(&config(vec![(10, 30), (190, 201)])) {
+            Err(TcFinderError::InvalidRange { start: 190, end: 201 }) => (),
+            other => panic!("{:?}", other),
+        }
+        match run(&config(vec![(30, 10)])) {
+            Err(TcFinderError::InvalidRange { start: 30, end: 10 }) => (),
+            other => panic!("{:?}", other),
+        }
+        match run(&ScanConfig { algorithms: vec![EncryptionAlgorithm::Camellia], ..config(vec![(10, 30)]) }) {
+            Err(TcFinderError::Crypto(_)) => (),
+            other => panic!("{:?}", other),
+        }
+        // The last sector of the partition is read without reading past the end.
+        let result = run(&config(vec![(190, 200)])).unwrap();
+        assert_eq!(result.headers.len(), 1);
+        assert_eq!(result.headers[0].sector, 200);
+        assert!(result.unreadable_sectors.is_empty());
+
+        // Sectors missing at the end of a truncated image are skipped like bad sectors.
+        image.truncate(196 * 512);
+        fs::write(&path, &image).unwrap();
+        let result = run(&config(vec![(190, 200)])).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(result.headers.is_empty());
+        assert_eq!(result.unreadable_sectors, [196, 197, 198, 199, 200]);
+    }
+
+    #[test]
+    fn test_pair_headers() {
+        let header = VolumeHeader { volume_size: 20_709_376, ..VolumeHeader::default() };
+        let derivation = KeyDerivation::new(VolumeFormat::TrueCrypt, Prf::Ripemd160, None, false).unwrap();
+        let algorithm = EncryptionAlgorithm::Aes;
+        let primary = [super::FoundHeader { sector: 100, header, derivation, algorithm, password_index: 0 }];
+        let backup = [
+            super::FoundHeader { sector: 100 + 40_704, header, derivation, algorithm, password_index: 0 },
+            super::FoundHeader { sector: 100 + 40_705, header, derivation, algorithm, password_index: 0 },
+            super::FoundHeader { sector: 100 + 40_704, header: VolumeHeader { volume_size: 512, ..header }, derivation, algorithm, password_index: 0 },
+        ];
+        let pairs = super::pair_headers(&primary, &backup, 512);
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(pairs[0].1.sector, 100 + 40_704);
+
+        let hidden = VolumeHeader { hidden_volume_size: 20_709_376, ..header };
+        let hidden_primary = [super::FoundHeader { sector: 100, header: hidden, derivation, algorithm, password_index: 0 }];
+        assert!(super::pair_headers(&hidden_primary, &backup, 512).is_empty());
+
+        assert_eq!(super::backup_header_sector(40_959, 512), Some(40_704));
+        assert_eq!(super::backup_header_sector(100, 512), None);
+    }
+
+    #[cfg(feature = "nightly")]
     #[bench]
     fn test_decrypt_bench(b: &mut Bencher) {
-        let password = "p4ssword";
-        let mut hmac: Hmac<Ripemd160> = Hmac::new(Ripemd160::new(), password.as_bytes());
-        b.iter(|| {super::decrypt(&mut hmac, &TC_HEADER[..64], &TC_HEADER[64..64 +16]);});
+        let derivation = KeyDerivation::new(VolumeFormat::TrueCrypt, Prf::Ripemd160, None, false).unwrap();
+        // The password is prepared once per scan, only the salt dependent part runs per sector.
+        let key = PasswordKey::new(b"p4ssword", derivation);
+        b.iter(|| {
+            super::decrypt(&EncryptionAlgorithm::Aes.expand_keys(LazyKeypool::new(&key, &TC_HEADER[..64]).get(64), Direction::Decrypt).unwrap(), &TC_HEADER[64..]).unwrap();
+        });
+    }
+
+    // Rejection of a wrong key pool without the key derivation, rejections per second = 10^9 / (ns/iter).
+    // test_full_reject_bench decrypts and parses all 448 bytes, test_fast_reject_bench only the first block.
+    #[cfg(feature = "nightly")]
+    #[bench]
+    fn test_full_reject_bench(b: &mut Bencher) {
+        let keypool: Vec<u8> = (0..192).map(|i| i as u8).collect();
+        b.iter(|| {
+            for &algorithm in &ALL_ALGORITHMS {
+                let decrypted = super::decrypt(&algorithm.expand_keys(&keypool, Direction::Decrypt).unwrap(), &TC_HEADER[64..]).unwrap();
+                assert!(VolumeHeader::parse(&decrypted).is_err());
+            }
+        });
+    }
+
+    #[cfg(feature = "nightly")]
+    #[bench]
+    fn test_fast_reject_bench(b: &mut Bencher) {
+        let derivation = KeyDerivation::new(VolumeFormat::VeraCrypt, Prf::Sha512, None, false).unwrap();
+        let keypool: Vec<u8> = (0..192).map(|i| i as u8).collect();
+        b.iter(|| {
+            for &algorithm in &ALL_ALGORITHMS {
+                assert!(super::decrypt_header(&keypool, algorithm, derivation, &TC_HEADER).is_none());
+            }
+        });
+    }
+
+    #[cfg(feature = "nightly")]
+    #[bench]
+    fn test_check_headers_bench(b: &mut Bencher) {
+        let derivation = KeyDerivation::new(VolumeFormat::TrueCrypt, Prf::Ripemd160, None, false).unwrap();
+        let keys = kdf::password_keys(&[b"p4ssword".to_vec()], &[derivation]);
+        // One batch of sectors, compare with kdf::BATCH_SIZE times test_decrypt_bench.
+        let headers: Vec<(u64, &[u8])> = (0..kdf::BATCH_SIZE as u64).map(|sector| (sector, &TC_HEADER[..])).collect();
+        b.iter(|| super::check_headers(&keys, &[EncryptionAlgorithm::Aes], &headers));
     }
 }
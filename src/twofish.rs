@@ -0,0 +1,261 @@
+// Twofish with 256 bit keys. The key dependent S-boxes are combined with the MDS matrix
+// into four lookup tables when the key is set.
+
+use cipher::{self, BlockCipher};
+use error::TcFinderError;
+
+const ROUNDS: usize = 16;
+const RHO: u32 = 0x0101_0101;
+
+// GF(2^8) reduction polynomials of the MDS and the RS matrix.
+const MDS_POLYNOMIAL: u16 = 0x169;
+const RS_POLYNOMIAL: u16 = 0x14D;
+
+const MDS: [[u8; 4]; 4] = [
+    [0x01, 0xEF, 0x5B, 0x5B],
+    [0x5B, 0xEF, 0xEF, 0x01],
+    [0xEF, 0x5B, 0x01, 0xEF],
+    [0xEF, 0x01, 0xEF, 0x5B],
+];
+
+const RS: [[u8; 8]; 4] = [
+    [0x01, 0xA4, 0x55, 0x87, 0x5A, 0x58, 0xDB, 0x9E],
+    [0xA4, 0x56, 0x82, 0xF3, 0x1E, 0xC6, 0x68, 0xE5],
+    [0x02, 0xA1, 0xFC, 0xC1, 0x47, 0xAE, 0x3D, 0x19],
+    [0xA4, 0x55, 0x87, 0x5A, 0x58, 0xDB, 0x9E, 0x03],
+];
+
+// 4 bit permutations t0..t3 the 8 bit permutations q0 and q1 are built from.
+const Q0_T: [[u8; 16]; 4] = [
+    [0x8, 0x1, 0x7, 0xD, 0x6, 0xF, 0x3, 0x2, 0x0, 0xB, 0x5, 0x9, 0xE, 0xC, 0xA, 0x4],
+    [0xE, 0xC, 0xB, 0x8, 0x1, 0x2, 0x3, 0x5, 0xF, 0x4, 0xA, 0x6, 0x7, 0x0, 0x9, 0xD],
+    [0xB, 0xA, 0x5, 0xE, 0x6, 0xD, 0x9, 0x0, 0xC, 0x8, 0xF, 0x3, 0x2, 0x4, 0x7, 0x1],
+    [0xD, 0x7, 0xF, 0x4, 0x1, 0x2, 0x6, 0xE, 0x9, 0xB, 0x3, 0x0, 0x8, 0x5, 0xC, 0xA],
+];
+const Q1_T: [[u8; 16]; 4] = [
+    [0x2, 0x8, 0xB, 0xD, 0xF, 0x7, 0x6, 0xE, 0x3, 0x1, 0x9, 0x4, 0x0, 0xA, 0xC, 0x5],
+    [0x1, 0xE, 0x2, 0xB, 0x4, 0xC, 0x3, 0x7, 0x6, 0xD, 0xA, 0x5, 0xF, 0x9, 0x0, 0x8],
+    [0x4, 0xC, 0x7, 0x5, 0x1, 0x6, 0x9, 0xA, 0x0, 0xE, 0xD, 0x8, 0x2, 0xB, 0x3, 0xF],
+    [0xB, 0x9, 0x5, 0x1, 0xC, 0x3, 0xD, 0xE, 0x6, 0x4, 0x7, 0xF, 0x2, 0x0, 0x8, 0xA],
+];
+
+const Q0: [u8; 256] = make_q(&Q0_T);
+const Q1: [u8; 256] = make_q(&Q1_T);
+
+const fn ror4(x: u8) -> u8 {
+    ((x >> 1) | (x << 3)) & 0xF
+}
+
+const fn make_q(t: &[[u8; 16]; 4]) -> [u8; 256] {
+    let mut q = [0u8; 256];
+    let mut x = 0;
+    while x < 256 {
+        let a0 = (x >> 4) as u8;
+        let b0 = (x & 0xF) as u8;
+        let a1 = a0 ^ b0;
+        let b1 = a0 ^ ror4(b0) ^ ((a0 << 3) & 0xF);
+        let a2 = t[0][a1 as usize];
+        let b2 = t[1][b1 as usize];
+        let a3 = a2 ^ b2;
+        let b3 = a2 ^ ror4(b2) ^ ((a2 << 3) & 0xF);
+        let a4 = t[2][a3 as usize];
+        let b4 = t[3][b3 as usize];
+        q[x] = (b4 << 4) | a4;
+        x += 1;
+    }
+    q
+}
+
+pub struct Twofish {
+    subkeys: [u32; 8 + 2 * ROUNDS],
+    // Key dependent S-box of each input byte, already multiplied with its MDS column.
+    sbox: [[u32; 256]; 4],
+}
+
+impl Twofish {
+    pub fn new(key: &[u8]) -> Twofish {
+        assert!(key.len() == 32, "Twofish: key length != 32");
+
+        let mut even = [0u32; 4];
+        let mut odd = [0u32; 4];
+        let mut s = [[0u8; 4]; 4];
+        for i in 0..4 {
+            even[i] = read_u32_le(&key[8 * i..8 * i + 4]);
+            odd[i] = read_u32_le(&key[8 * i + 4..8 * i + 8]);
+            // S is used in reverse order.
+            s[3 - i] = rs_multiply(&key[8 * i..8 * i + 8]);
+        }
+
+        let mut subkeys = [0u32; 8 + 2 * ROUNDS];
+        for i in 0..subkeys.len() / 2 {
+            let a = h(2 * i as u32 * RHO, &words_to_bytes(&even));
+            let b = h((2 * i as u32 + 1) * RHO, &words_to_bytes(&odd)).rotate_left(8);
+            subkeys[2 * i] = a.wrapping_add(b);
+            subkeys[2 * i + 1] = a.wrapping_add(b.wrapping_mul(2)).rotate_left(9);
+        }
+
+        let mut sbox = [[0u32; 256]; 4];
+        for x in 0..256 {
+            let y = sboxes(x as u8, &s);
+            for (i, table) in sbox.iter_mut().enumerate() {
+                table[x] = mds_column(i, y[i]);
+            }
+        }
+        Twofish { subkeys, sbox }
+    }
+
+    fn g(&self, x: u32) -> u32 {
+        self.sbox[0][(x & 0xFF) as usize]
+            ^ self.sbox[1][(x >> 8 & 0xFF) as usize]
+            ^ self.sbox[2][(x >> 16 & 0xFF) as usize]
+            ^ self.sbox[3][(x >> 24) as usize]
+    }
+
+    fn round_function(&self, r0: u32, r1: u32, round: usize) -> (u32, u32) {
+        let t0 = self.g(r0);
+        let t1 = self.g(r1.rotate_left(8));
+        let f0 = t0.wrapping_add(t1).wrapping_add(self.subkeys[2 * round + 8]);
+        let f1 = t0.wrapping_add(t1.wrapping_mul(2)).wrapping_add(self.subkeys[2 * round + 9]);
+        (f0, f1)
+    }
+}
+
+impl BlockCipher for Twofish {
+    fn encrypt_block(&self, block: &mut [u8]) -> Result<(), TcFinderError> {
+        cipher::check_block(block)?;
+        let mut r = load_block(block);
+        for (i, word) in r.iter_mut().enumerate() {
+            *word ^= self.subkeys[i];
+        }
+        for round in 0..ROUNDS {
+            let (f0, f1) = self.round_function(r[0], r[1], round);
+            let r2 = (r[2] ^ f0).rotate_right(1);
+            let r3 = r[3].rotate_left(1) ^ f1;
+            r = [r2, r3, r[0], r[1]];
+        }
+        // Undo the last swap.
+        let mut out = [r[2], r[3], r[0], r[1]];
+        for (i, word) in out.iter_mut().enumerate() {
+            *word ^= self.subkeys[4 + i];
+        }
+        store_block(out, block);
+        Ok(())
+    }
+
+    fn decrypt_block(&self, block: &mut [u8]) -> Result<(), TcFinderError> {
+        cipher::check_block(block)?;
+        let c = load_block(block);
+        let mut r = [0u32; 4];
+        for i in 0..4 {
+            r[i] = c[(i + 2) % 4] ^ self.subkeys[4 + (i + 2) % 4];
+        }
+        for round in (0..ROUNDS).rev() {
+            let (f0, f1) = self.round_function(r[2], r[3], round);
+            let r2 = (r[0].rotate_left(1)) ^ f0;
+            let r3 = (r[1] ^ f1).rotate_right(1);
+            r = [r[2], r[3], r2, r3];
+        }
+        for (i, word) in r.iter_mut().enumerate() {
+            *word ^= self.subkeys[i];
+        }
+        store_block(r, block);
+        Ok(())
+    }
+}
+
+// Key dependent permutations of one input byte, before the MDS multiplication.
+// list holds the four key words as bytes, list[0] is applied last.
+fn sboxes(x: u8, list: &[[u8; 4]; 4]) -> [u8; 4] {
+    let mut y = [x; 4];
+    y = [Q1[y[0] as usize] ^ list[3][0], Q0[y[1] as usize] ^ list[3][1],
+         Q0[y[2] as usize] ^ list[3][2], Q1[y[3] as usize] ^ list[3][3]];
+    y = [Q1[y[0] as usize] ^ list[2][0], Q1[y[1] as usize] ^ list[2][1],
+         Q0[y[2] as usize] ^ list[2][2], Q0[y[3] as usize] ^ list[2][3]];
+    y = [Q0[y[0] as usize] ^ list[1][0], Q1[y[1] as usize] ^ list[1][1],
+         Q0[y[2] as usize] ^ list[1][2], Q1[y[3] as usize] ^ list[1][3]];
+    y = [Q0[y[0] as usize] ^ list[0][0], Q0[y[1] as usize] ^ list[0][1],
+         Q1[y[2] as usize] ^ list[0][2], Q1[y[3] as usize] ^ list[0][3]];
+    [Q1[y[0] as usize], Q0[y[1] as usize], Q1[y[2] as usize], Q0[y[3] as usize]]
+}
+
+fn h(x: u32, list: &[[u8; 4]; 4]) -> u32 {
+    let mut result = 0;
+    for (i, &y) in x.to_le_bytes().iter().enumerate() {
+        result ^= mds_column(i, sboxes(y, list)[i]);
+    }
+    result
+}
+
+fn mds_column(column: usize, y: u8) -> u32 {
+    (0..4).fold(0, |acc, row| acc | u32::from(gf_multiply(MDS[row][column], y, MDS_POLYNOMIAL)) << (8 * row))
+}
+
+fn rs_multiply(key: &[u8]) -> [u8; 4] {
+    let mut s = [0u8; 4];
+    for (row, byte) in s.iter_mut().enumerate() {
+        *byte = key.iter().enumerate().fold(0, |acc, (j, &k)| acc ^ gf_multiply(RS[row][j], k, RS_POLYNOMIAL));
+    }
+    s
+}
+
+fn gf_multiply(mut a: u8, mut b: u8, polynomial: u16) -> u8 {
+    let mut result = 0u8;
+    while b != 0 {
+        if b & 1 == 1 {
+            result ^= a;
+        }
+        a = if a & 0x80 != 0 { ((u16::from(a) << 1) ^ polynomial) as u8 } else { a << 1 };
+        b >>= 1;
+    }
+    result
+}
+
+fn words_to_bytes(words: &[u32; 4]) -> [[u8; 4]; 4] {
+    [words[0].to_le_bytes(), words[1].to_le_bytes(), words[2].to_le_bytes(), words[3].to_le_bytes()]
+}
+
+fn load_block(block: &[u8]) -> [u32; 4] {
+    assert!(block.len() == 16, "Twofish: block length != 16");
+    [read_u32_le(&block[0..4]), read_u32_le(&block[4..8]), read_u32_le(&block[8..12]), read_u32_le(&block[12..16])]
+}
+
+fn store_block(x: [u32; 4], block: &mut [u8]) {
+    for (word, chunk) in x.iter().zip(block.chunks_mut(4)) {
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+}
+
+fn read_u32_le(bytes: &[u8]) -> u32 {
+    bytes.iter().rev().fold(0, |acc, &b| acc << 8 | u32::from(b))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::Twofish;
+    use cipher::BlockCipher;
+
+    fn hex_str_to_vec(s: &str) -> Vec<u8> {
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i+2], 16).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_twofish() {
+        let test_cases = [
+            // Twofish paper, 256 bit key test vectors
+            ("0000000000000000000000000000000000000000000000000000000000000000",
+             "00000000000000000000000000000000", "57ff739d4dc92c1bd7fc01700cc8216f"),
+            ("0123456789abcdeffedcba987654321000112233445566778899aabbccddeeff",
+             "00000000000000000000000000000000", "37527be0052334b89f0cfccae87cfa20"),
+        ];
+
+        for &(key, plain, cipher) in &test_cases {
+            let twofish = Twofish::new(&hex_str_to_vec(key));
+            let mut block = hex_str_to_vec(plain);
+            twofish.encrypt_block(&mut block).unwrap();
+            assert_eq!(block, hex_str_to_vec(cipher));
+            twofish.decrypt_block(&mut block).unwrap();
+            assert_eq!(block, hex_str_to_vec(plain));
+        }
+    }
+}
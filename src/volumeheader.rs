@@ -0,0 +1,164 @@
+use std::fmt;
+
+use crc32;
+use error::TcFinderError;
+
+pub const HEADER_SIZE: usize = 512;
+pub const SALT_SIZE: usize = 64;
+// Everything after the salt is encrypted.
+pub const ENCRYPTED_SIZE: usize = HEADER_SIZE - SALT_SIZE;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum VolumeFormat {
+    #[default]
+    TrueCrypt,
+    VeraCrypt,
+}
+
+impl VolumeFormat {
+    // First 4 decrypted bytes: "TRUE" or "VERA".
+    pub fn magic(&self) -> [u8; 4] {
+        match *self {
+            VolumeFormat::TrueCrypt => [0x54, 0x52, 0x55, 0x45],
+            VolumeFormat::VeraCrypt => [0x56, 0x45, 0x52, 0x41],
+        }
+    }
+}
+
+// Offsets into the decrypted part of the header (header offset - 64).
+// All integers are stored big-endian.
+const OFFSET_VERSION: usize = 4;
+const OFFSET_REQUIRED_PROGRAM_VERSION: usize = 6;
+const OFFSET_KEY_AREA_CRC: usize = 8;
+const OFFSET_VOLUME_CREATION_TIME: usize = 12;
+const OFFSET_HEADER_CREATION_TIME: usize = 20;
+const OFFSET_HIDDEN_VOLUME_SIZE: usize = 28;
+const OFFSET_VOLUME_SIZE: usize = 36;
+const OFFSET_MASTER_KEY_SCOPE_OFFSET: usize = 44;
+const OFFSET_MASTER_KEY_SCOPE_SIZE: usize = 52;
+const OFFSET_FLAGS: usize = 60;
+const OFFSET_SECTOR_SIZE: usize = 64;
+const OFFSET_HEADER_CRC: usize = 188;
+const OFFSET_KEY_AREA: usize = 192;
+
+// Header version 4 (TrueCrypt 6.0) added the CRC over the header fields,
+// version 5 (TrueCrypt 7.0) the sector size.
+const FIRST_VERSION_WITH_HEADER_CRC: u16 = 4;
+const FIRST_VERSION_WITH_SECTOR_SIZE: u16 = 5;
+const DEFAULT_SECTOR_SIZE: u32 = 512;
+
+// Parsed fields of a decrypted TrueCrypt or VeraCrypt volume header.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct VolumeHeader {
+    pub format: VolumeFormat,
+    pub version: u16,
+    pub required_program_version: u16,
+    pub key_area_crc: u32,
+    // Windows FILETIME, zero if not set.
+    pub volume_creation_time: u64,
+    pub header_creation_time: u64,
+    pub hidden_volume_size: u64,
+    pub volume_size: u64,
+    pub master_key_scope_offset: u64,
+    pub master_key_scope_size: u64,
+    pub flags: u32,
+    pub sector_size: u32,
+}
+
+impl VolumeHeader {
+    // Parses the 448 decrypted bytes following the salt.
+    // Fails unless the magic bytes and both checksums match.
+    pub fn parse(decrypted: &[u8]) -> Result<VolumeHeader, TcFinderError> {
+        if decrypted.len() != ENCRYPTED_SIZE {
+            return Err(TcFinderError::InvalidHeader("Decrypted header has wrong size."));
+        }
+        let format = if decrypted[..4] == VolumeFormat::TrueCrypt.magic() {
+            VolumeFormat::TrueCrypt
+        } else if decrypted[..4] == VolumeFormat::VeraCrypt.magic() {
+            VolumeFormat::VeraCrypt
+        } else {
+            return Err(TcFinderError::InvalidHeader("Magic bytes do not match."));
+        };
+
+        let version = read_u16(decrypted, OFFSET_VERSION);
+        let key_area_crc = read_u32(decrypted, OFFSET_KEY_AREA_CRC);
+        if crc32::checksum(&decrypted[OFFSET_KEY_AREA..]) != key_area_crc {
+            return Err(TcFinderError::InvalidHeader("CRC32 of the key area does not match."));
+        }
+        if version >= FIRST_VERSION_WITH_HEADER_CRC
+            && crc32::checksum(&decrypted[..OFFSET_HEADER_CRC]) != read_u32(decrypted, OFFSET_HEADER_CRC)
+        {
+            return Err(TcFinderError::InvalidHeader("CRC32 of the header does not match."));
+        }
+
+        let sector_size = if version >= FIRST_VERSION_WITH_SECTOR_SIZE {
+            read_u32(decrypted, OFFSET_SECTOR_SIZE)
+        } else {
+            DEFAULT_SECTOR_SIZE
+        };
+
+        Ok(VolumeHeader {
+            format,
+            version,
+            required_program_version: read_u16(decrypted, OFFSET_REQUIRED_PROGRAM_VERSION),
+            key_area_crc,
+            volume_creation_time: read_u64(decrypted, OFFSET_VOLUME_CREATION_TIME),
+            header_creation_time: read_u64(decrypted, OFFSET_HEADER_CREATION_TIME),
+            hidden_volume_size: read_u64(decrypted, OFFSET_HIDDEN_VOLUME_SIZE),
+            volume_size: read_u64(decrypted, OFFSET_VOLUME_SIZE),
+            master_key_scope_offset: read_u64(decrypted, OFFSET_MASTER_KEY_SCOPE_OFFSET),
+            master_key_scope_size: read_u64(decrypted, OFFSET_MASTER_KEY_SCOPE_SIZE),
+            flags: read_u32(decrypted, OFFSET_FLAGS),
+            sector_size,
+        })
+    }
+
+    // Only the header of a hidden volume has the hidden volume size set.
+    pub fn is_hidden(&self) -> bool {
+        self.hidden_volume_size != 0
+    }
+}
+
+impl fmt::Display for VolumeHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "VolumeHeader {{\n\
+                   \tformat: {:?},\n\
+                   \tversion: {},\n\
+                   \trequired_program_version: {:X}.{:X},\n\
+                   \tkey_area_crc: {:08X},\n\
+                   \tvolume_creation_time: {},\n\
+                   \theader_creation_time: {},\n\
+                   \thidden_volume_size: {},\n\
+                   \tvolume_size: {},\n\
+                   \tmaster_key_scope_offset: {},\n\
+                   \tmaster_key_scope_size: {},\n\
+                   \tflags: {:08X},\n\
+                   \tsector_size: {},\n\
+                   }}",
+               self.format,
+               self.version,
+               self.required_program_version >> 8,
+               self.required_program_version & 0xFF,
+               self.key_area_crc,
+               self.volume_creation_time,
+               self.header_creation_time,
+               self.hidden_volume_size,
+               self.volume_size,
+               self.master_key_scope_offset,
+               self.master_key_scope_size,
+               self.flags,
+               self.sector_size)
+    }
+}
+
+fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from(buf[offset]) << 8 | u16::from(buf[offset + 1])
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    buf[offset..offset + 4].iter().fold(0, |acc, &b| acc << 8 | u32::from(b))
+}
+
+fn read_u64(buf: &[u8], offset: usize) -> u64 {
+    buf[offset..offset + 8].iter().fold(0, |acc, &b| acc << 8 | u64::from(b))
+}
@@ -0,0 +1,45 @@
+// Wordlists: one password per line, taken as raw bytes so passwords in any encoding are tried as they are.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+use error::TcFinderError;
+
+// Line endings may be '\n' or "\r\n". Empty lines are skipped.
+pub fn read_wordlist(path: &str) -> Result<Vec<Vec<u8>>, TcFinderError> {
+    let file = File::open(path).map_err(|e| TcFinderError::file(path, e))?;
+    let mut passwords = Vec::new();
+    for line in BufReader::new(file).split(b'\n') {
+        let mut line = line.map_err(|e| TcFinderError::file(path, e))?;
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        if !line.is_empty() {
+            passwords.push(line);
+        }
+    }
+    Ok(passwords)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use error::TcFinderError;
+
+    #[test]
+    fn test_read_wordlist() {
+        let path = env::temp_dir().join("tcfinder_test_wordlist.txt");
+        let path = path.to_str().unwrap();
+        fs::write(path, b"p4ssword\r\n\nsecret\n\xe4\xf6\xfc\nlast").unwrap();
+        let passwords = super::read_wordlist(path).unwrap();
+        assert_eq!(passwords, [b"p4ssword".to_vec(), b"secret".to_vec(), b"\xe4\xf6\xfc".to_vec(), b"last".to_vec()]);
+        fs::remove_file(path).unwrap();
+
+        match super::read_wordlist(path) {
+            Err(TcFinderError::File { path: ref error_path, .. }) => assert_eq!(error_path, path),
+            other => panic!("{:?}", other),
+        }
+    }
+}
@@ -24,25 +24,46 @@
 //
 // Original source: http://www.bjrn.se/code/pytruecrypt/xtspy.txt
 
+use cipher::{BlockCipher, BLOCK_SIZE};
+use error::TcFinderError;
 use gf2n;
 
-pub fn xts_decrypt(key1: &[u8], key2: &[u8], block: &[u8]) -> [u8; 16] {
-    let n_txt = [0u8; 16];
-    let e_k2_n = super::aes::encrypt_block(&n_txt, key2).expect("Encrypting block failed!");
-
-    let a_i = [0u8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
-    let e_mul_a = gf2n::gfmul_simd(&e_k2_n, &a_i);
+// Decrypts a data unit (usually a sector) in place. The data length has to be a multiple of 16 bytes,
+// the tweak is advanced per block starting at block index 0.
+// cipher1 is keyed with the primary key, cipher2 with the secondary (tweak) key.
+pub fn decrypt_data_unit<C: BlockCipher + ?Sized>(cipher1: &C, cipher2: &C, data_unit: u64, data: &mut [u8])
+                                                   -> Result<(), TcFinderError> {
+    process_data_unit(cipher2, data_unit, data, |block| cipher1.decrypt_block(block))
+}
 
-    let xored = xor_bytes_16(&e_mul_a, block);
+// Encrypts a data unit in place. Counterpart of decrypt_data_unit.
+pub fn encrypt_data_unit<C: BlockCipher + ?Sized>(cipher1: &C, cipher2: &C, data_unit: u64, data: &mut [u8])
+                                                   -> Result<(), TcFinderError> {
+    process_data_unit(cipher2, data_unit, data, |block| cipher1.encrypt_block(block))
+}
 
-    let mut key1_decrypted = super::aes::decrypt_block(&xored, key1)
-        .expect("Decrypting block failed!");
+fn process_data_unit<C, F>(cipher2: &C, data_unit: u64, data: &mut [u8], cipher: F) -> Result<(), TcFinderError>
+    where C: BlockCipher + ?Sized, F: Fn(&mut [u8]) -> Result<(), TcFinderError>
+{
+    if !data.len().is_multiple_of(BLOCK_SIZE) {
+        return Err(TcFinderError::Crypto("Data unit length is not a multiple of 16 bytes."));
+    }
 
-    if key1_decrypted.len() < 16 {
-        key1_decrypted = arr_size_to_16b(&key1_decrypted);
+    // Data unit number as 128 bit little-endian.
+    let mut tweak = [0u8; BLOCK_SIZE];
+    for (i, byte) in tweak.iter_mut().take(8).enumerate() {
+        *byte = (data_unit >> (i * 8)) as u8;
     }
+    // Tweak of block 0 is E_K2(n) * alpha^0 = E_K2(n), every following block multiplies it by alpha.
+    cipher2.encrypt_block(&mut tweak)?;
 
-    xor_bytes_16(&e_mul_a, &key1_decrypted)
+    for block in data.chunks_mut(BLOCK_SIZE) {
+        let mut xored = xor_bytes_16(&tweak, block);
+        cipher(&mut xored)?;
+        block.copy_from_slice(&xor_bytes_16(&tweak, &xored));
+        gf2n::mul_alpha(&mut tweak);
+    }
+    Ok(())
 }
 
 fn xor_bytes_16(a: &[u8], b: &[u8]) -> [u8; 16] {
@@ -54,11 +75,92 @@ fn xor_bytes_16(a: &[u8], b: &[u8]) -> [u8; 16] {
     result
 }
 
-fn arr_size_to_16b(arr: &[u8]) -> Vec<u8> {
-    let mut temp = vec![0u8; 16];
-    let diff = 16 - arr.len();
-    for (i, item) in arr.iter().enumerate() {
-        temp[i+diff] = *item;
+
+#[cfg(test)]
+mod tests {
+    use aes::Aes256;
+    use cipher::Direction;
+
+    struct TestCase {
+        pub data_unit: u64,
+        pub ciphertext: Vec<u8>,
+    }
+
+    const KEY1: &str = "2718281828459045235360287471352662497757247093699959574966967627";
+    const KEY2: &str = "3141592653589793238462643383279502884197169399375105820974944592";
+
+    fn hex_str_to_vec(s: &str) -> Vec<u8> {
+        assert_eq!(s.len() % 2, 0);
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i+2], 16).unwrap()).collect()
+    }
+
+    // Plaintext of the 512 byte test vectors: 00 01 .. FF 00 01 .. FF
+    fn plaintext() -> Vec<u8> {
+        (0..512).map(|i| i as u8).collect()
+    }
+
+    fn create_testcases() -> Vec<TestCase> {
+        vec![
+    // IEEE 1619-2007, Vector 10
+    TestCase {
+        data_unit: 0xff,
+        ciphertext: hex_str_to_vec("1c3b3a102f770386e4836c99e370cf9bea00803f5e482357a4ae12d414a3e63b\
+                                    5d31e276f8fe4a8d66b317f9ac683f44680a86ac35adfc3345befecb4bb188fd\
+                                    5776926c49a3095eb108fd1098baec70aaa66999a72a82f27d848b21d4a741b0\
+                                    c5cd4d5fff9dac89aeba122961d03a757123e9870f8acf1000020887891429ca\
+                                    2a3e7a7d7df7b10355165c8b9a6d0a7de8b062c4500dc4cd120c0f7418dae3d0\
+                                    b5781c34803fa75421c790dfe1de1834f280d7667b327f6c8cd7557e12ac3a0f\
+                                    93ec05c52e0493ef31a12d3d9260f79a289d6a379bc70c50841473d1a8cc81ec\
+                                    583e9645e07b8d9670655ba5bbcfecc6dc3966380ad8fecb17b6ba02469a020a\
+                                    84e18e8f84252070c13e9f1f289be54fbc481457778f616015e1327a02b140f1\
+                                    505eb309326d68378f8374595c849d84f4c333ec4423885143cb47bd71c5edae\
+                                    9be69a2ffeceb1bec9de244fbe15992b11b77c040f12bd8f6a975a44a0f90c29\
+                                    a9abc3d4d893927284c58754cce294529f8614dcd2aba991925fedc4ae74ffac\
+                                    6e333b93eb4aff0479da9a410e4450e0dd7ae4c6e2910900575da401fc07059f\
+                                    645e8b7e9bfdef33943054ff84011493c27b3429eaedb4ed5376441a77ed4385\
+                                    1ad77f16f541dfd269d50d6a5f14fb0aab1cbb4c1550be97f7ab4066193c4caa\
+                                    773dad38014bd2092fa755c824bb5e54c4f36ffda9fcea70b9c6e693e148c151"),
+    },
+    // IEEE 1619-2007, Vector 11
+    TestCase {
+        data_unit: 0xffff,
+        ciphertext: hex_str_to_vec("77a31251618a15e6b92d1d66dffe7b50b50bad552305ba0217a610688eff7e11\
+                                    e1d0225438e093242d6db274fde801d4cae06f2092c728b2478559df58e837c2\
+                                    469ee4a4fa794e4bbc7f39bc026e3cb72c33b0888f25b4acf56a2a9804f1ce6d\
+                                    3d6e1dc6ca181d4b546179d55544aa7760c40d06741539c7e3cd9d2f6650b201\
+                                    3fd0eeb8c2b8e3d8d240ccae2d4c98320a7442e1c8d75a42d6e6cfa4c2eca179\
+                                    8d158c7aecdf82490f24bb9b38e108bcda12c3faf9a21141c3613b58367f922a\
+                                    aa26cd22f23d708dae699ad7cb40a8ad0b6e2784973dcb605684c08b8d6998c6\
+                                    9aac049921871ebb65301a4619ca80ecb485a31d744223ce8ddc2394828d6a80\
+                                    470c092f5ba413c3378fa6054255c6f9df4495862bbb3287681f931b687c888a\
+                                    bf844dfc8fc28331e579928cd12bd2390ae123cf03818d14dedde5c0c24c8ab0\
+                                    18bfca75ca096f2d531f3d1619e785f1ada437cab92e980558b3dce1474afb75\
+                                    bfedbf8ff54cb2618e0244c9ac0d3c66fb51598cd2db11f9be39791abe447c63\
+                                    094f7c453b7ff87cb5bb36b7c79efb0872d17058b83b15ab0866ad8a58656c5a\
+                                    7e20dbdf308b2461d97c0ec0024a2715055249cf3b478ddd4740de654f75ca68\
+                                    6e0d7345c69ed50cdc2a8b332b1f8824108ac937eb050585608ee734097fc090\
+                                    54fbff89eeaeea791f4a7ab1f9868294a4f9e27b42af8100cb9d59cef9645803"),
+    },
+        ]
+    }
+
+    #[test]
+    fn test_encrypt_data_unit() {
+        let (cipher1, cipher2) = (Aes256::new(&hex_str_to_vec(KEY1), Direction::Encrypt), Aes256::new(&hex_str_to_vec(KEY2), Direction::Encrypt));
+        for test_case in &create_testcases() {
+            let mut data = plaintext();
+            super::encrypt_data_unit(&cipher1, &cipher2, test_case.data_unit, &mut data).unwrap();
+            assert_eq!(data, test_case.ciphertext);
+        }
+    }
+
+    #[test]
+    fn test_decrypt_data_unit() {
+        let (cipher1, cipher2) = (Aes256::new(&hex_str_to_vec(KEY1), Direction::Decrypt), Aes256::new(&hex_str_to_vec(KEY2), Direction::Encrypt));
+        for test_case in &create_testcases() {
+            let mut data = test_case.ciphertext.clone();
+            super::decrypt_data_unit(&cipher1, &cipher2, test_case.data_unit, &mut data).unwrap();
+            assert_eq!(data, plaintext());
+        }
     }
-    temp
 }